@@ -1,13 +1,29 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::sysvar::instructions::{
+    self as sysvar_instructions, load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke;
+use anchor_lang::Discriminator;
 use anchor_spl::{
-    associated_token::AssociatedToken,
-    token::{self, Mint, Token, TokenAccount, Transfer, MintTo, Burn},
+    associated_token::{self, get_associated_token_address, AssociatedToken},
+    token::{self, Mint, Token, TokenAccount, Transfer, MintTo, Burn, CloseAccount},
+    token_2022::spl_token_2022::{self, extension::{BaseStateWithExtensions, StateWithExtensions, transfer_fee::TransferFeeConfig}},
+    token_interface::{self, TokenInterface, TransferChecked},
 };
 
+mod oracle;
 mod utils;
 use utils::{init_liquidity, get_optimal_b, get_optimal_a, 
     cacl_liquidity, get_token_amount, get_amount_b_out, get_amount_a_out, MIN_LIQUIDITY,
-MINT_SEED, POOL_SEED};
+MINT_SEED, POOL_SEED, cacl_liquidity_multi, stable_get_d, get_stable_amount_out,
+MULTI_POOL_SEED, ORDER_SEED, DCA_SEED, BATCH_SEED, INTENT_SEED, BATCH_EXPIRY_SLOTS, COMMIT_SEED, STATS_SEED, WITHDRAW_SEED, ALLOWLIST_SEED, COMMIT_MIN_SLOTS, COMMIT_EXPIRY_SLOTS, MAX_BATCH_LEGS, MAX_MULTI_TOKENS, MAX_AMP, MAX_FEE_BPS, DEFAULT_FEE_BPS, MAX_VOLATILITY_ACCUMULATOR,
+decay_linear, get_dynamic_fee_bps, get_price_move_bps, get_zap_swap_amount, get_flash_loan_fee,
+check_fee_adjusted_k, get_min_output, get_referral_amount, get_batch_clearing, get_spot_price, update_twap, get_oracle_price, get_fee_amount, check_lp_value, check_stable_lp_value, BPS_DENOMINATOR,
+AMM_SPACE, POOL_SPACE, POOL_STATS_SPACE, POOL_RESERVES_END, POOL_VERSION};
+use oracle::load_pyth_price;
 
 declare_id!("ESKCtzJykZmkZ158YbUXRsaKJn1CxQ1KxpHEKVRZY3At");
 // bump 存储
@@ -26,12 +42,29 @@ pub mod spl_swap {
         Ok(())
     }
 
-    pub fn create_pool(ctx: Context<CreatePool>) ->Result<()> {
+    pub fn set_max_referral_bps(ctx: Context<UpdateAmm>, max_referral_bps: u16) -> Result<()> {
+        require!(max_referral_bps as u64 <= BPS_DENOMINATOR, CustError::InvalidReferralFee);
+        let amm = &mut ctx.accounts.amm;
+        amm.max_referral_bps = max_referral_bps;
+        emit!(AMMEvent{message: "max referral updated".to_string(), creator: amm.admin});
+        Ok(())
+    }
+
+    // fee_on_transfer_a/b flag mints whose transfers can deliver less than the amount sent, or whose balances rebase
+    pub fn create_pool(ctx: Context<CreatePool>, fee_on_transfer_a: bool, fee_on_transfer_b: bool) ->Result<()> {
+        require!(fee_on_transfer_a || !has_transfer_fee(&ctx.accounts.mint_a)?, CustError::FeeOnTransferNotFlagged);
+        require!(fee_on_transfer_b || !has_transfer_fee(&ctx.accounts.mint_b)?, CustError::FeeOnTransferNotFlagged);
         let pool_account = &mut ctx.accounts.pool_account;
         pool_account.amm = ctx.accounts.amm.admin;
         pool_account.mint_a = ctx.accounts.mint_a.key();
         pool_account.mint_b = ctx.accounts.mint_b.key();
         pool_account.bump = ctx.bumps.pool_account;
+        pool_account.version = POOL_VERSION;
+        pool_account.fee_on_transfer_a = fee_on_transfer_a;
+        pool_account.fee_on_transfer_b = fee_on_transfer_b;
+        let pool_stats = &mut ctx.accounts.pool_stats;
+        pool_stats.pool = pool_account.key();
+        pool_stats.bump = ctx.bumps.pool_stats;
         // event emit
         emit!(AMMEvent{message: "pool created".to_string(), creator: pool_account.amm});
         Ok(())
@@ -41,67 +74,284 @@ pub mod spl_swap {
         Ok(())
     }
 
+    // grows a pool and its amm written by an older program version to the current layout,
+    // appended fields start zeroed, which leaves every feature added since disabled
+    pub fn migrate_pool(ctx: Context<MigratePool>) -> Result<()> {
+        let accounts = &ctx.accounts;
+        realloc_account(&accounts.amm, &Amm::DISCRIMINATOR, AMM_SPACE, &accounts.admin, &accounts.system_program)?;
+        let old_len = accounts.pool_account.data_len();
+        realloc_account(&accounts.pool_account, &Pool::DISCRIMINATOR, POOL_SPACE, &accounts.admin, &accounts.system_program)?;
+
+        let mut data = accounts.pool_account.try_borrow_mut_data()?;
+        let mut pool_account = Pool::try_deserialize(&mut &data[..])?;
+        // reserves were the vault balances before they were tracked
+        if old_len < POOL_RESERVES_END {
+            pool_account.reserve_a = accounts.pool_account_a.amount;
+            pool_account.reserve_b = accounts.pool_account_b.amount;
+        }
+        pool_account.version = POOL_VERSION;
+        pool_account.try_serialize(&mut &mut data[..])?;
+        drop(data);
+
+        let pool_stats = &mut ctx.accounts.pool_stats;
+        pool_stats.pool = ctx.accounts.pool_account.key();
+        pool_stats.bump = ctx.bumps.pool_stats;
+        emit!(AMMEvent{message: "pool migrated".to_string(), creator: pool_account.amm});
+        Ok(())
+    }
+
+    pub fn set_dynamic_fee(ctx: Context<UpdatePool>, enabled: bool, base_fee_bps: u16, max_fee_bps: u16, variable_fee_control: u32, decay_period: i64) -> Result<()> {
+        require!(base_fee_bps <= max_fee_bps && max_fee_bps <= MAX_FEE_BPS, CustError::InvalidFee);
+        require!(decay_period > 0, CustError::InvalidFee);
+        let pool_account = &mut ctx.accounts.pool_account;
+        pool_account.dynamic_fee = enabled;
+        pool_account.base_fee_bps = base_fee_bps;
+        pool_account.max_fee_bps = max_fee_bps;
+        pool_account.variable_fee_control = variable_fee_control;
+        pool_account.decay_period = decay_period;
+        pool_account.volatility_accumulator = 0;
+        emit!(AMMEvent{message: "dynamic fee updated".to_string(), creator: pool_account.amm});
+        Ok(())
+    }
+
+    pub fn set_flash_loan_fee(ctx: Context<UpdatePool>, fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, CustError::InvalidFee);
+        let pool_account = &mut ctx.accounts.pool_account;
+        pool_account.flash_fee_bps = fee_bps;
+        emit!(AMMEvent{message: "flash loan fee updated".to_string(), creator: pool_account.amm});
+        Ok(())
+    }
+
+    // lends from one vault, a flash_repay for the same pool must follow later in this transaction
+    pub fn flash_borrow(ctx: Context<FlashBorrow>, amount: u64, borrow_a: bool) -> Result<()> {
+        let reserve = if borrow_a { ctx.accounts.pool_account.reserve_a } else { ctx.accounts.pool_account.reserve_b };
+        require!(amount > 0 && amount <= reserve, CustError::InsufficientLiquidity);
+        let mint = if borrow_a { ctx.accounts.mint_a.key() } else { ctx.accounts.mint_b.key() };
+        require_keys_eq!(ctx.accounts.receiver_account.mint, mint, CustError::InvalidMint);
+
+        // borrow must be a top level instruction so the repay can be found by introspection
+        let instructions = ctx.accounts.instructions.to_account_info();
+        let current_index = load_current_index_checked(&instructions)? as usize;
+        let current_ix = load_instruction_at_checked(current_index, &instructions)?;
+        require_keys_eq!(current_ix.program_id, crate::ID, CustError::FlashLoanCpi);
+        let pool_key = ctx.accounts.pool_account.key();
+        let mut index = current_index + 1;
+        let mut repay_found = false;
+        while let Ok(ix) = load_instruction_at_checked(index, &instructions) {
+            if ix.program_id == crate::ID
+                && ix.data.get(..8) == Some(&instruction::FlashRepay::DISCRIMINATOR[..])
+                && ix.accounts.first().map(|meta| meta.pubkey) == Some(pool_key) {
+                repay_found = true;
+                break;
+            }
+            index += 1;
+        }
+        require!(repay_found, CustError::FlashLoanNoRepay);
+
+        let fee = get_flash_loan_fee(amount, ctx.accounts.pool_account.flash_fee_bps);
+        let authority_bump = ctx.accounts.pool_account.bump;
+        let authority_seeds = &[
+            &ctx.accounts.pool_account.amm.to_bytes(),
+            &ctx.accounts.mint_a.key().to_bytes(),
+            &ctx.accounts.mint_b.key().to_bytes(),
+            POOL_SEED,
+            &[authority_bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        let pool_account_out = if borrow_a {
+            ctx.accounts.pool_account_a.to_account_info()
+        } else {
+            ctx.accounts.pool_account_b.to_account_info()
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: pool_account_out,
+                    to: ctx.accounts.receiver_account.to_account_info(),
+                    authority: ctx.accounts.pool_account.to_account_info(),
+                },
+                signer_seeds
+            ),
+            amount,
+        )?;
+
+        let pool_account = &mut ctx.accounts.pool_account;
+        pool_account.flash_loan_active = true;
+        pool_account.flash_loan_a = borrow_a;
+        pool_account.flash_loan_amount = amount;
+        emit!(FlashLoanEvent{message: "flash borrow".to_string(), operator: ctx.accounts.borrower.key(), amount, fee});
+        Ok(())
+    }
+
+    pub fn flash_repay(ctx: Context<FlashRepay>) -> Result<()> {
+        let pool_account = &ctx.accounts.pool_account;
+        require!(pool_account.flash_loan_active, CustError::FlashLoanNotActive);
+        let mint = if pool_account.flash_loan_a { ctx.accounts.mint_a.key() } else { ctx.accounts.mint_b.key() };
+        require_keys_eq!(ctx.accounts.repayer_account.mint, mint, CustError::InvalidMint);
+        let amount = pool_account.flash_loan_amount;
+        let fee = get_flash_loan_fee(amount, pool_account.flash_fee_bps);
+
+        let pool_account_in = if pool_account.flash_loan_a {
+            ctx.accounts.pool_account_a.to_account_info()
+        } else {
+            ctx.accounts.pool_account_b.to_account_info()
+        };
+        token::transfer(
+            CpiContext::new(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.repayer_account.to_account_info(),
+                    to: pool_account_in,
+                    authority: ctx.accounts.repayer.to_account_info(),
+                },
+            ),
+            amount + fee,
+        )?;
+
+        // the fee stays in the vault and accrues to LPs
+        let pool_account = &mut ctx.accounts.pool_account;
+        if pool_account.flash_loan_a {
+            pool_account.reserve_a += fee;
+        } else {
+            pool_account.reserve_b += fee;
+        }
+        pool_account.flash_loan_active = false;
+        pool_account.flash_loan_amount = 0;
+        emit!(FlashLoanEvent{message: "flash repay".to_string(), operator: ctx.accounts.repayer.key(), amount, fee});
+        Ok(())
+    }
+
+    // permissionless, absorbs tokens sent straight to the vaults into the reserves
+    pub fn sync(ctx: Context<SyncPool>) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        pool_account.reserve_a = ctx.accounts.pool_account_a.amount;
+        pool_account.reserve_b = ctx.accounts.pool_account_b.amount;
+        emit!(SyncEvent{message: "sync".to_string(), reserve_a: pool_account.reserve_a, reserve_b: pool_account.reserve_b});
+        Ok(())
+    }
+
+    // permissionless, sends the vault balances above the reserves to the recipient accounts
+    pub fn skim(ctx: Context<SkimPool>) -> Result<()> {
+        let amount_a = ctx.accounts.pool_account_a.amount.saturating_sub(ctx.accounts.pool_account.reserve_a);
+        let amount_b = ctx.accounts.pool_account_b.amount.saturating_sub(ctx.accounts.pool_account.reserve_b);
+
+        let authority_bump = ctx.accounts.pool_account.bump;
+        let authority_seeds = &[
+            &ctx.accounts.pool_account.amm.to_bytes(),
+            &ctx.accounts.mint_a.key().to_bytes(),
+            &ctx.accounts.mint_b.key().to_bytes(),
+            POOL_SEED,
+            &[authority_bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        if amount_a > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(ctx.accounts.token_program_a.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.pool_account_a.to_account_info(),
+                        mint: ctx.accounts.mint_a.to_account_info(),
+                        to: ctx.accounts.recipient_account_a.to_account_info(),
+                        authority: ctx.accounts.pool_account.to_account_info(),
+                    },
+                    signer_seeds
+                ),
+                amount_a,
+                ctx.accounts.mint_a.decimals,
+            )?;
+        }
+        if amount_b > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(ctx.accounts.token_program_b.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.pool_account_b.to_account_info(),
+                        mint: ctx.accounts.mint_b.to_account_info(),
+                        to: ctx.accounts.recipient_account_b.to_account_info(),
+                        authority: ctx.accounts.pool_account.to_account_info(),
+                    },
+                    signer_seeds
+                ),
+                amount_b,
+                ctx.accounts.mint_b.decimals,
+            )?;
+        }
+        emit!(SkimEvent{message: "skim".to_string(), amount_a, amount_b});
+        Ok(())
+    }
 
-    pub fn add_liquidity(ctx: Context<AddLiquidity>, amount_a: u64, amount_b: u64, min_amount_a: u64, min_amount_b: u64) -> Result<()> {
-        let pool_account_a = &mut ctx.accounts.pool_account_a;
-        let pool_account_b = &mut ctx.accounts.pool_account_b;
-        let mut acctual_a:u64;
+    pub fn add_liquidity(ctx: Context<AddLiquidity>, amount_a: u64, amount_b: u64, min_amount_a: u64, min_amount_b: u64, min_lp_out: u64) -> Result<()> {
+        sync_flagged_reserves(&mut ctx.accounts.pool_account, &ctx.accounts.pool_account_a, &ctx.accounts.pool_account_b);
+        let before_liquidity = (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b, ctx.accounts.mint_liquidity.supply);
+        let acctual_a:u64;
         let acctual_b:u64;
-        let liquidity_to_add: u64 ;
-        if pool_account_a.amount == 0 && pool_account_b.amount == 0 {
+        if ctx.accounts.mint_liquidity.supply == 0 {
             acctual_a = amount_a;
             acctual_b = amount_b;
-            // Computing the amount of liquidity about to be deposited
-            let mut liquidity = init_liquidity(amount_a, amount_b);
-            require!(liquidity > MIN_LIQUIDITY, CustError::DepositTooSmall);
-            liquidity -= MIN_LIQUIDITY;
-            liquidity_to_add = liquidity;
         }else {
-            acctual_a = amount_a;
             // optimalb = amout_a * poolb /poola
-            let optimalb = get_optimal_b(amount_a, ctx.accounts.pool_account_a.amount, ctx.accounts.pool_account_b.amount);
-            if optimalb < amount_b && optimalb >=min_amount_b{
+            let optimalb = get_optimal_b(amount_a, ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b);
+            if optimalb <= amount_b {
+                require!(optimalb >= min_amount_b, CustError::InsufficentOutputTokenB);
+                acctual_a = amount_a;
                 acctual_b = optimalb;
             }else{
-                acctual_b = amount_b;
                 // optimala = amout_b * poola /poolb
-                let optimala = get_optimal_a(amount_b, ctx.accounts.pool_account_a.amount, ctx.accounts.pool_account_b.amount);
-                require!(optimala < amount_a, CustError::InsufficentInputTokenA);
+                let optimala = get_optimal_a(amount_b, ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b);
+                require!(optimala <= amount_a, CustError::InsufficentInputTokenA);
                 require!(optimala >= min_amount_a, CustError::InsufficentOutputTokenA);
                 acctual_a = optimala;
+                acctual_b = amount_b;
             }
-
-            // record amount_a before transfer
-            let pool_account_a = ctx.accounts.pool_account_a.amount;
-            let pool_account_b = ctx.accounts.pool_account_b.amount;
-
-            // Computing the amount of liquidity to be deposited
-
-            let total_supply = ctx.accounts.mint_liquidity.supply;
-            liquidity_to_add = cacl_liquidity(total_supply, acctual_a, pool_account_a, acctual_b, pool_account_b);
         }
         // transfer from depositor's token account to pool_account
-        token::transfer(
-            CpiContext::new(ctx.accounts.token_program.to_account_info(),
-                Transfer {
+        token_interface::transfer_checked(
+            CpiContext::new(ctx.accounts.token_program_a.to_account_info(),
+                TransferChecked {
                     from: ctx.accounts.depositor_account_a.to_account_info(),
+                    mint: ctx.accounts.mint_a.to_account_info(),
                     to: ctx.accounts.pool_account_a.to_account_info(),
                     authority: ctx.accounts.depositor.to_account_info(),
                 },
             ),
             acctual_a,
+            ctx.accounts.mint_a.decimals,
         )?;
-        token::transfer(
+        token_interface::transfer_checked(
             CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
+                ctx.accounts.token_program_b.to_account_info(),
+                TransferChecked {
                     from: ctx.accounts.depositor_account_b.to_account_info(),
+                    mint: ctx.accounts.mint_b.to_account_info(),
                     to: ctx.accounts.pool_account_b.to_account_info(),
                     authority: ctx.accounts.depositor.to_account_info(),
                 },
             ),
             acctual_b,
+            ctx.accounts.mint_b.decimals,
         )?;
+        // flagged mints are credited with what reached the vaults
+        let received_a = get_received_amount(ctx.accounts.pool_account.fee_on_transfer_a, &mut ctx.accounts.pool_account_a, acctual_a)?;
+        let received_b = get_received_amount(ctx.accounts.pool_account.fee_on_transfer_b, &mut ctx.accounts.pool_account_b, acctual_b)?;
+
+        let liquidity_to_add: u64 ;
+        let mut locked_liquidity: u64 = 0;
+        if ctx.accounts.mint_liquidity.supply == 0 {
+            // Computing the amount of liquidity about to be deposited
+            let mut liquidity = init_liquidity(received_a, received_b);
+            require!(liquidity > MIN_LIQUIDITY, CustError::DepositTooSmall);
+            // MIN_LIQUIDITY is minted to the pool's own LP account and never leaves it
+            liquidity -= MIN_LIQUIDITY;
+            locked_liquidity = MIN_LIQUIDITY;
+            liquidity_to_add = liquidity;
+        }else {
+            // record reserves before transfer
+            let pool_account_a = ctx.accounts.pool_account.reserve_a;
+            let pool_account_b = ctx.accounts.pool_account.reserve_b;
+
+            // Computing the amount of liquidity to be deposited
+
+            let total_supply = ctx.accounts.mint_liquidity.supply;
+            liquidity_to_add = cacl_liquidity(total_supply, received_a, pool_account_a, received_b, pool_account_b);
+        }
+        require!(liquidity_to_add >= min_lp_out, CustError::InsufficientLiquidityMinted);
 
         // Mint the liquidity to user
 
@@ -127,14 +377,126 @@ pub mod spl_swap {
             ),
             liquidity_to_add,
         )?;
+        if locked_liquidity > 0 {
+            token::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    MintTo {
+                        mint: ctx.accounts.mint_liquidity.to_account_info(),
+                        to: ctx.accounts.pool_account_liquidity.to_account_info(),
+                        authority: ctx.accounts.pool_account.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                locked_liquidity,
+            )?;
+        }
+        let pool_account = &mut ctx.accounts.pool_account;
+        pool_account.reserve_a += received_a;
+        pool_account.reserve_b += received_b;
+        let pool_stats = &mut ctx.accounts.pool_stats;
+        pool_stats.deposit_count += 1;
+        pool_stats.last_trade_ts = Clock::get()?.unix_timestamp;
+        check_liquidity_invariant(&ctx.accounts.pool_account, &mut ctx.accounts.pool_account_a, &mut ctx.accounts.pool_account_b, &mut ctx.accounts.mint_liquidity, before_liquidity)?;
         //event
-        emit!(AddLiquidityEvent{message:"add liquidity".to_string(), operator: ctx.accounts.depositor.key(), amount_a: acctual_a, amount_b: acctual_b});
+        emit!(AddLiquidityEvent{message:"add liquidity".to_string(), operator: ctx.accounts.depositor.key(), amount_a: received_a, amount_b: received_b, lp_token: liquidity_to_add});
+        Ok(())
+    }
+
+    pub fn add_liquidity_single(ctx: Context<AddLiquiditySingle>, input_mint: Pubkey, amount_in: u64, min_lp_out: u64) -> Result<()> {
+        let input_a = input_mint == ctx.accounts.mint_a.key();
+        require!(input_a || input_mint == ctx.accounts.mint_b.key(), CustError::InvalidMint);
+        let before_liquidity = (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b, ctx.accounts.mint_liquidity.supply);
+        let current_amount_a = ctx.accounts.pool_account.reserve_a;
+        let current_amount_b = ctx.accounts.pool_account.reserve_b;
+        require!(current_amount_a > 0 && current_amount_b > 0, CustError::EmptyPool);
+
+        // swap part of the input inside the pool, then deposit both sides at the post-swap ratio
+        let now = Clock::get()?.unix_timestamp;
+        let (fee_bps, volatility) = get_swap_fee_bps(&ctx.accounts.pool_account, now);
+        let total_supply = ctx.accounts.mint_liquidity.supply;
+        let swap_amount: u64;
+        let liquidity_to_add: u64;
+        let after: (u64, u64);
+        if input_a {
+            swap_amount = get_zap_swap_amount(amount_in, current_amount_a, fee_bps);
+            let swap_output = get_amount_b_out(swap_amount, current_amount_a, current_amount_b, fee_bps);
+            after = (current_amount_a + swap_amount, current_amount_b - swap_output);
+            liquidity_to_add = cacl_liquidity(total_supply, amount_in - swap_amount, after.0, swap_output, after.1);
+        }else {
+            swap_amount = get_zap_swap_amount(amount_in, current_amount_b, fee_bps);
+            let swap_output = get_amount_a_out(swap_amount, current_amount_a, current_amount_b, fee_bps);
+            after = (current_amount_a - swap_output, current_amount_b + swap_amount);
+            liquidity_to_add = cacl_liquidity(total_supply, swap_output, after.0, amount_in - swap_amount, after.1);
+        }
+        require!(liquidity_to_add > 0, CustError::DepositTooSmall);
+        require!(liquidity_to_add >= min_lp_out, CustError::InsufficientLiquidityMinted);
+        let (amount_a, amount_b) = if input_a { (amount_in, 0) } else { (0, amount_in) };
+        let deposited = (current_amount_a + amount_a, current_amount_b + amount_b);
+        check_oracle_price(&ctx.accounts.pool_account, &ctx.accounts.oracle, (ctx.accounts.mint_a.decimals, ctx.accounts.mint_b.decimals), deposited, now)?;
+        if !check_circuit_breaker(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), deposited, now)? {
+            return Ok(());
+        }
+
+        // the swapped output never leaves the pool, so only the input is transferred
+        let pool_account_in = if input_a {
+            ctx.accounts.pool_account_a.to_account_info()
+        } else {
+            ctx.accounts.pool_account_b.to_account_info()
+        };
+        token::transfer(
+            CpiContext::new(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.depositor_account_in.to_account_info(),
+                    to: pool_account_in,
+                    authority: ctx.accounts.depositor.to_account_info(),
+                },
+            ),
+            amount_in,
+        )?;
+
+        let authority_bump = ctx.bumps.pool_account;
+        let authority_seeds = &[
+            &ctx.accounts.pool_account.amm.to_bytes(),
+            &ctx.accounts.mint_a.key().to_bytes(),
+            &ctx.accounts.mint_b.key().to_bytes(),
+            POOL_SEED,
+            &[authority_bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.mint_liquidity.to_account_info(),
+                    to: ctx.accounts.depositor_account_liquidity.to_account_info(),
+                    authority: ctx.accounts.pool_account.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            liquidity_to_add,
+        )?;
+        record_swap_volatility(&mut ctx.accounts.pool_account, volatility, (current_amount_a, current_amount_b), after, now);
+        (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b) = deposited;
+        check_liquidity_invariant(&ctx.accounts.pool_account, &mut ctx.accounts.pool_account_a, &mut ctx.accounts.pool_account_b, &mut ctx.accounts.mint_liquidity, before_liquidity)?;
+        ctx.accounts.pool_stats.deposit_count += 1;
+        // only the inner swap counts as a trade
+        let fee = get_fee_amount(swap_amount, fee_bps);
+        if input_a {
+            record_swap_stats(&mut ctx.accounts.pool_stats, 1, (swap_amount as u128, (current_amount_b - after.1) as u128), (fee, 0), now);
+        } else {
+            record_swap_stats(&mut ctx.accounts.pool_stats, 1, ((current_amount_a - after.0) as u128, swap_amount as u128), (0, fee), now);
+        }
+        emit!(AddLiquidityEvent{message:"add liquidity single".to_string(), operator: ctx.accounts.depositor.key(), amount_a, amount_b, lp_token: liquidity_to_add});
         Ok(())
     }
 
+    // a flagged output mint may deliver less than min_amount_a / min_amount_b, the minimums bound what the vaults send
     pub fn remove_liquidity(ctx: Context<RemoveLiquidity>, liquidity: u64, min_amount_a: u64, min_amount_b: u64)->Result<()>{
-        let amount_a = get_token_amount(liquidity, ctx.accounts.mint_liquidity.supply, ctx.accounts.pool_account_a.amount);
-        let amount_b = get_token_amount(liquidity, ctx.accounts.mint_liquidity.supply, ctx.accounts.pool_account_b.amount);
+        sync_flagged_reserves(&mut ctx.accounts.pool_account, &ctx.accounts.pool_account_a, &ctx.accounts.pool_account_b);
+        let before_liquidity = (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b, ctx.accounts.mint_liquidity.supply);
+        let amount_a = get_token_amount(liquidity, ctx.accounts.mint_liquidity.supply, ctx.accounts.pool_account.reserve_a);
+        let amount_b = get_token_amount(liquidity, ctx.accounts.mint_liquidity.supply, ctx.accounts.pool_account.reserve_b);
         require!(amount_a>=min_amount_a, CustError::InsufficentOutputTokenA);
         require!(amount_b>=min_amount_b, CustError::InsufficentOutputTokenB);
 
@@ -148,260 +510,3922 @@ pub mod spl_swap {
             &[authority_bump],
         ];
         let signer_seeds = &[&authority_seeds[..]];
-        token::transfer(
-            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
-                Transfer {
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(ctx.accounts.token_program_a.to_account_info(),
+                TransferChecked {
                     from: ctx.accounts.pool_account_a.to_account_info(),
+                    mint: ctx.accounts.mint_a.to_account_info(),
                     to: ctx.accounts.depositor_account_a.to_account_info(),
                     authority: ctx.accounts.pool_account.to_account_info(),
                 },
                 signer_seeds
             ),
             amount_a,
+            ctx.accounts.mint_a.decimals,
         )?;
 
-        token::transfer(
-            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
-                Transfer {
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(ctx.accounts.token_program_b.to_account_info(),
+                TransferChecked {
                     from: ctx.accounts.pool_account_b.to_account_info(),
+                    mint: ctx.accounts.mint_b.to_account_info(),
                     to: ctx.accounts.depositor_account_b.to_account_info(),
                     authority: ctx.accounts.pool_account.to_account_info(),
                 },
                 signer_seeds
             ),
             amount_b,
+            ctx.accounts.mint_b.decimals,
         )?;
 
-        token::burn(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Burn{
-                    mint: ctx.accounts.mint_liquidity.to_account_info(),
-                    from: ctx.accounts.depositor_account_liquidity.to_account_info(),
-                    authority:ctx.accounts.depositor.to_account_info()
-                }
-            ),
-            liquidity)?;
+        if ctx.accounts.pool_account.withdraw_cooldown > 0 {
+            // the LP comes out of the escrow of an earlier request_withdraw once the cooldown is over
+            let withdraw_request = ctx.accounts.withdraw_request.as_ref().ok_or(CustError::WithdrawNotRequested)?;
+            let withdraw_escrow = ctx.accounts.withdraw_escrow.as_ref().ok_or(CustError::WithdrawNotRequested)?;
+            require_keys_eq!(
+                withdraw_escrow.key(),
+                get_associated_token_address(&withdraw_request.key(), &ctx.accounts.mint_liquidity.key()),
+                CustError::WithdrawNotRequested
+            );
+            let now = Clock::get()?.unix_timestamp;
+            require!(now >= withdraw_request.requested_ts + ctx.accounts.pool_account.withdraw_cooldown, CustError::WithdrawCooldownActive);
+            require!(liquidity <= withdraw_escrow.amount, CustError::InsufficientLiquidity);
+            let pool_key = ctx.accounts.pool_account.key();
+            let request_seeds = &[
+                pool_key.as_ref(),
+                withdraw_request.owner.as_ref(),
+                WITHDRAW_SEED,
+                &[withdraw_request.bump],
+            ];
+            let request_signer = &[&request_seeds[..]];
+            token::burn(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Burn{
+                        mint: ctx.accounts.mint_liquidity.to_account_info(),
+                        from: withdraw_escrow.to_account_info(),
+                        authority: withdraw_request.to_account_info(),
+                    },
+                    request_signer
+                ),
+                liquidity)?;
+            if liquidity == withdraw_escrow.amount {
+                token::close_account(
+                    CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                        CloseAccount {
+                            account: withdraw_escrow.to_account_info(),
+                            destination: ctx.accounts.depositor.to_account_info(),
+                            authority: withdraw_request.to_account_info(),
+                        },
+                        request_signer
+                    ),
+                )?;
+                withdraw_request.close(ctx.accounts.depositor.to_account_info())?;
+            }
+        } else {
+            token::burn(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Burn{
+                        mint: ctx.accounts.mint_liquidity.to_account_info(),
+                        from: ctx.accounts.depositor_account_liquidity.to_account_info(),
+                        authority:ctx.accounts.depositor.to_account_info()
+                    }
+                ),
+                liquidity)?;
+        }
+        let pool_account = &mut ctx.accounts.pool_account;
+        let before = (pool_account.reserve_a, pool_account.reserve_b);
+        check_outflow_limit(pool_account, before, (before.0 - amount_a, before.1 - amount_b), Clock::get()?.unix_timestamp)?;
+        pool_account.reserve_a -= amount_a;
+        pool_account.reserve_b -= amount_b;
+        let pool_stats = &mut ctx.accounts.pool_stats;
+        pool_stats.withdraw_count += 1;
+        pool_stats.last_trade_ts = Clock::get()?.unix_timestamp;
+        check_liquidity_invariant(&ctx.accounts.pool_account, &mut ctx.accounts.pool_account_a, &mut ctx.accounts.pool_account_b, &mut ctx.accounts.mint_liquidity, before_liquidity)?;
         emit!(RemoveLiquidityEvent{message:"add liquidity".to_string(), operator: ctx.accounts.depositor.key(), lp_token:liquidity});
         Ok(())
     }
 
-    pub fn swap_extacttoken_fortoken(ctx: Context<Swap>, amount: u64, min_output: u64, output_b:bool)->Result<()>{
-        let current_amount_a = ctx.accounts.pool_account_a.amount;
-        let current_amount_b = ctx.accounts.pool_account_b.amount;
-        let output:u64;
-        if output_b{
-            let output_amount = get_amount_b_out(amount, current_amount_a, current_amount_b);
-            require!(output_amount >=min_output, CustError::InsufficientOutputAmount);
-            output = output_amount;
-            // transfer input to pool
-            token::transfer(
+    // escrows LP until the pool's withdraw cooldown is over, remove_liquidity then burns it from the escrow
+    pub fn request_withdraw(ctx: Context<RequestWithdraw>, liquidity: u64) -> Result<()> {
+        require!(ctx.accounts.pool_account.withdraw_cooldown > 0, CustError::WithdrawCooldownDisabled);
+        require!(liquidity > 0, CustError::InsufficientLiquidity);
+        token::transfer(
             CpiContext::new(ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.depositor_account_a.to_account_info(),
-                        to: ctx.accounts.pool_account_a.to_account_info(),
-                        authority: ctx.accounts.depositor.to_account_info(),
-                    },
-                ),
-                amount,
-            )?;
-            // transfer output to depositor
-            let authority_bump = ctx.bumps.pool_account;
-            let authority_seeds = &[
-                &ctx.accounts.pool_account.amm.to_bytes(),
-                &ctx.accounts.mint_a.key().to_bytes(),
-                &ctx.accounts.mint_b.key().to_bytes(),
-                POOL_SEED,
-                &[authority_bump],
-            ];
-            let signer_seeds = &[&authority_seeds[..]];
-            token::transfer(
+                Transfer {
+                    from: ctx.accounts.owner_account_liquidity.to_account_info(),
+                    to: ctx.accounts.withdraw_escrow.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            liquidity,
+        )?;
+        let withdraw_request = &mut ctx.accounts.withdraw_request;
+        withdraw_request.owner = ctx.accounts.owner.key();
+        withdraw_request.pool = ctx.accounts.pool_account.key();
+        withdraw_request.requested_ts = Clock::get()?.unix_timestamp;
+        withdraw_request.bump = ctx.bumps.withdraw_request;
+        emit!(RemoveLiquidityEvent{message: "withdraw requested".to_string(), operator: withdraw_request.owner, lp_token: liquidity});
+        Ok(())
+    }
+
+    // returns the escrowed LP, the liquidity stays in the pool
+    pub fn cancel_withdraw(ctx: Context<CancelWithdraw>) -> Result<()> {
+        let withdraw_request = &ctx.accounts.withdraw_request;
+        let request_seeds = &[
+            withdraw_request.pool.as_ref(),
+            withdraw_request.owner.as_ref(),
+            WITHDRAW_SEED,
+            &[withdraw_request.bump],
+        ];
+        let request_signer = &[&request_seeds[..]];
+        let liquidity = ctx.accounts.withdraw_escrow.amount;
+        token::transfer(
             CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
                 Transfer {
-                    from: ctx.accounts.pool_account_b.to_account_info(),
-                    to: ctx.accounts.depositor_account_b.to_account_info(),
-                    authority: ctx.accounts.pool_account.to_account_info(),
+                    from: ctx.accounts.withdraw_escrow.to_account_info(),
+                    to: ctx.accounts.owner_account_liquidity.to_account_info(),
+                    authority: ctx.accounts.withdraw_request.to_account_info(),
                 },
-                signer_seeds
+                request_signer
             ),
-            output_amount,
-            )?;
+            liquidity,
+        )?;
+        token::close_account(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.withdraw_escrow.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.withdraw_request.to_account_info(),
+                },
+                request_signer
+            ),
+        )?;
+        emit!(RemoveLiquidityEvent{message: "withdraw cancelled".to_string(), operator: withdraw_request.owner, lp_token: liquidity});
+        Ok(())
+    }
 
+    pub fn remove_liquidity_one_token(ctx: Context<RemoveLiquiditySingle>, lp_amount: u64, output_mint: Pubkey, min_out: u64) -> Result<()> {
+        let output_a = output_mint == ctx.accounts.mint_a.key();
+        require!(output_a || output_mint == ctx.accounts.mint_b.key(), CustError::InvalidMint);
+        let before_liquidity = (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b, ctx.accounts.mint_liquidity.supply);
+        let current_amount_a = ctx.accounts.pool_account.reserve_a;
+        let current_amount_b = ctx.accounts.pool_account.reserve_b;
+        let amount_a = get_token_amount(lp_amount, ctx.accounts.mint_liquidity.supply, current_amount_a);
+        let amount_b = get_token_amount(lp_amount, ctx.accounts.mint_liquidity.supply, current_amount_b);
 
-        }else{
-            let output_amount = get_amount_a_out(amount, current_amount_a, current_amount_b);
-            require!(output_amount >=min_output, CustError::InsufficientOutputAmount);
-            output = output_amount;
-            // transfer input to pool
-            token::transfer(
-            CpiContext::new(ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.depositor_account_b.to_account_info(),
-                        to: ctx.accounts.pool_account_b.to_account_info(),
-                        authority: ctx.accounts.depositor.to_account_info(),
-                    },
-                ),
-                amount,
-            )?;
-            // transfer output to depositor
-            let authority_bump = ctx.bumps.pool_account;
-            let authority_seeds = &[
-                &ctx.accounts.pool_account.amm.to_bytes(),
-                &ctx.accounts.mint_a.key().to_bytes(),
-                &ctx.accounts.mint_b.key().to_bytes(),
-                POOL_SEED,
-                &[authority_bump],
-            ];
-            let signer_seeds = &[&authority_seeds[..]];
-            token::transfer(
+        // the unwanted side is swapped back into the pool at the post-withdrawal reserves
+        let now = Clock::get()?.unix_timestamp;
+        let (fee_bps, volatility) = get_swap_fee_bps(&ctx.accounts.pool_account, now);
+        let before = (current_amount_a - amount_a, current_amount_b - amount_b);
+        let output: u64;
+        let after: (u64, u64);
+        if output_a {
+            output = amount_a + get_amount_a_out(amount_b, before.0, before.1, fee_bps);
+            after = (current_amount_a - output, current_amount_b);
+        }else {
+            output = amount_b + get_amount_b_out(amount_a, before.0, before.1, fee_bps);
+            after = (current_amount_a, current_amount_b - output);
+        }
+        require!(output >= min_out, CustError::InsufficientOutputAmount);
+        check_oracle_price(&ctx.accounts.pool_account, &ctx.accounts.oracle, (ctx.accounts.mint_a.decimals, ctx.accounts.mint_b.decimals), after, now)?;
+        if !check_circuit_breaker(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), after, now)? {
+            return Ok(());
+        }
+
+        let authority_bump = ctx.bumps.pool_account;
+        let authority_seeds = &[
+            &ctx.accounts.pool_account.amm.to_bytes(),
+            &ctx.accounts.mint_a.key().to_bytes(),
+            &ctx.accounts.mint_b.key().to_bytes(),
+            POOL_SEED,
+            &[authority_bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        let pool_account_out = if output_a {
+            ctx.accounts.pool_account_a.to_account_info()
+        } else {
+            ctx.accounts.pool_account_b.to_account_info()
+        };
+        token::transfer(
             CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
                 Transfer {
-                    from: ctx.accounts.pool_account_a.to_account_info(),
-                    to: ctx.accounts.depositor_account_a.to_account_info(),
+                    from: pool_account_out,
+                    to: ctx.accounts.depositor_account_out.to_account_info(),
                     authority: ctx.accounts.pool_account.to_account_info(),
                 },
                 signer_seeds
             ),
-            output_amount,
-            )?;
+            output,
+        )?;
+
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn{
+                    mint: ctx.accounts.mint_liquidity.to_account_info(),
+                    from: ctx.accounts.depositor_account_liquidity.to_account_info(),
+                    authority:ctx.accounts.depositor.to_account_info()
+                }
+            ),
+            lp_amount)?;
+        check_outflow_limit(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), after, now)?;
+        record_swap_volatility(&mut ctx.accounts.pool_account, volatility, before, after, now);
+        (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b) = after;
+        check_liquidity_invariant(&ctx.accounts.pool_account, &mut ctx.accounts.pool_account_a, &mut ctx.accounts.pool_account_b, &mut ctx.accounts.mint_liquidity, before_liquidity)?;
+        ctx.accounts.pool_stats.withdraw_count += 1;
+        // only the inner swap counts as a trade
+        if output_a {
+            let fee = get_fee_amount(amount_b, fee_bps);
+            record_swap_stats(&mut ctx.accounts.pool_stats, 1, ((output - amount_a) as u128, amount_b as u128), (0, fee), now);
+        } else {
+            let fee = get_fee_amount(amount_a, fee_bps);
+            record_swap_stats(&mut ctx.accounts.pool_stats, 1, (amount_a as u128, (output - amount_b) as u128), (fee, 0), now);
         }
-        // event
-        emit!(SwapEvent{message:"swap token".to_string(), operator:ctx.accounts.depositor.key(), input: amount, output: output });
+        emit!(RemoveLiquidityEvent{message:"remove liquidity one token".to_string(), operator: ctx.accounts.depositor.key(), lp_token: lp_amount});
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct CreateAmm<'info> {
-    #[account(
-        init,
-        payer = payer,
-        space = 8+32+1,
-        seeds = [payer.key().as_ref()],
-        bump,
-    )]
-    pub amm: Box<Account<'info, Amm>>,
+    // remaining accounts: [referrer token account] (optional)
+    pub fn swap_extacttoken_fortoken<'info>(ctx: Context<'_, '_, 'info, 'info, Swap<'info>>, amount: u64, min_output: u64, output_b:bool, max_price_impact_bps: Option<u16>, referral_bps: u16)->Result<()>{
+        sync_flagged_reserves(&mut ctx.accounts.pool_account, &ctx.accounts.pool_account_a, &ctx.accounts.pool_account_b);
+        let current_amount_a = ctx.accounts.pool_account.reserve_a;
+        let current_amount_b = ctx.accounts.pool_account.reserve_b;
+        let now = Clock::get()?.unix_timestamp;
+        let (fee_bps, volatility) = get_swap_fee_bps(&ctx.accounts.pool_account, now);
+        let (depositor_account_in, pool_account_in, mint_in, token_program_in, decimals_in) = if output_b {
+            (ctx.accounts.depositor_account_a.to_account_info(), ctx.accounts.pool_account_a.to_account_info(), ctx.accounts.mint_a.to_account_info(), ctx.accounts.token_program_a.to_account_info(), ctx.accounts.mint_a.decimals)
+        } else {
+            (ctx.accounts.depositor_account_b.to_account_info(), ctx.accounts.pool_account_b.to_account_info(), ctx.accounts.mint_b.to_account_info(), ctx.accounts.token_program_b.to_account_info(), ctx.accounts.mint_b.decimals)
+        };
+        let (pool_account_out, depositor_account_out, mint_out, token_program_out, decimals_out) = if output_b {
+            (ctx.accounts.pool_account_b.to_account_info(), ctx.accounts.depositor_account_b.to_account_info(), ctx.accounts.mint_b.to_account_info(), ctx.accounts.token_program_b.to_account_info(), ctx.accounts.mint_b.decimals)
+        } else {
+            (ctx.accounts.pool_account_a.to_account_info(), ctx.accounts.depositor_account_a.to_account_info(), ctx.accounts.mint_a.to_account_info(), ctx.accounts.token_program_a.to_account_info(), ctx.accounts.mint_a.decimals)
+        };
+        let input_fee_on_transfer = if output_b { ctx.accounts.pool_account.fee_on_transfer_a } else { ctx.accounts.pool_account.fee_on_transfer_b };
 
-    #[account(mut)]
+        // the referral share is carved out of the input fee, the rest of the fee stays with LPs
+        let mut referrer: Option<Pubkey> = None;
+        let mut referral_amount: u64 = 0;
+        if !ctx.remaining_accounts.is_empty() {
+            require!(referral_bps <= ctx.accounts.amm.max_referral_bps, CustError::InvalidReferralFee);
+            referral_amount = get_referral_amount(amount, fee_bps, referral_bps);
+        }
+        let mut amount_to_pool = amount - referral_amount;
+        // a flagged input is moved first and the swap is priced on what reached the vault
+        if input_fee_on_transfer {
+            token_interface::transfer_checked(
+                CpiContext::new(token_program_in.clone(),
+                    TransferChecked {
+                        from: depositor_account_in.clone(),
+                        mint: mint_in.clone(),
+                        to: pool_account_in.clone(),
+                        authority: ctx.accounts.depositor.to_account_info(),
+                    },
+                ),
+                amount_to_pool,
+                decimals_in,
+            )?;
+            amount_to_pool = if output_b {
+                get_received_amount(true, &mut ctx.accounts.pool_account_a, amount_to_pool)?
+            } else {
+                get_received_amount(true, &mut ctx.accounts.pool_account_b, amount_to_pool)?
+            };
+        }
+        let amount_in = amount_to_pool + referral_amount;
+        let output = if output_b {
+            get_amount_b_out(amount_in, current_amount_a, current_amount_b, fee_bps)
+        } else {
+            get_amount_a_out(amount_in, current_amount_a, current_amount_b, fee_bps)
+        };
+        require!(output >= min_output, CustError::InsufficientOutputAmount);
+        let after = if output_b {
+            (current_amount_a + amount_to_pool, current_amount_b - output)
+        } else {
+            (current_amount_a - output, current_amount_b + amount_to_pool)
+        };
+        if let Some(max_price_impact_bps) = max_price_impact_bps {
+            let price_impact = get_price_move_bps(current_amount_a, current_amount_b, after.0, after.1);
+            require!(price_impact <= max_price_impact_bps as u64, CustError::PriceImpactTooHigh);
+        }
+        check_oracle_price(&ctx.accounts.pool_account, &ctx.accounts.oracle, (ctx.accounts.mint_a.decimals, ctx.accounts.mint_b.decimals), after, now)?;
+        if !check_circuit_breaker(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), after, now)? {
+            // a flagged input already sits in the vault, so a trigger can only reject
+            require!(!input_fee_on_transfer, CustError::CircuitBreakerTriggered);
+            return Ok(());
+        }
+
+        if let Some(referrer_info) = ctx.remaining_accounts.first() {
+            let referrer_account = InterfaceAccount::<token_interface::TokenAccount>::try_from(referrer_info)?;
+            require_keys_eq!(referrer_account.mint, mint_in.key(), CustError::InvalidMint);
+            if referral_amount > 0 {
+                token_interface::transfer_checked(
+                    CpiContext::new(token_program_in.clone(),
+                        TransferChecked {
+                            from: depositor_account_in.clone(),
+                            mint: mint_in.clone(),
+                            to: referrer_info.clone(),
+                            authority: ctx.accounts.depositor.to_account_info(),
+                        },
+                    ),
+                    referral_amount,
+                    decimals_in,
+                )?;
+            }
+            referrer = Some(referrer_account.owner);
+        }
+        // transfer input to pool
+        if !input_fee_on_transfer {
+            token_interface::transfer_checked(
+                CpiContext::new(token_program_in,
+                    TransferChecked {
+                        from: depositor_account_in,
+                        mint: mint_in,
+                        to: pool_account_in,
+                        authority: ctx.accounts.depositor.to_account_info(),
+                    },
+                ),
+                amount_to_pool,
+                decimals_in,
+            )?;
+        }
+        // transfer output to depositor
+        let authority_bump = ctx.bumps.pool_account;
+        let authority_seeds = &[
+            &ctx.accounts.pool_account.amm.to_bytes(),
+            &ctx.accounts.mint_a.key().to_bytes(),
+            &ctx.accounts.mint_b.key().to_bytes(),
+            POOL_SEED,
+            &[authority_bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(token_program_out,
+                TransferChecked {
+                    from: pool_account_out,
+                    mint: mint_out,
+                    to: depositor_account_out,
+                    authority: ctx.accounts.pool_account.to_account_info(),
+                },
+                signer_seeds
+            ),
+            output,
+            decimals_out,
+        )?;
+        check_outflow_limit(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), after, now)?;
+        record_swap_volatility(&mut ctx.accounts.pool_account, volatility, (current_amount_a, current_amount_b), after, now);
+        (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b) = after;
+        check_swap_invariant(&ctx.accounts.pool_account, &mut ctx.accounts.pool_account_a, &mut ctx.accounts.pool_account_b, (current_amount_a, current_amount_b))?;
+        let fee = get_fee_amount(amount_in, fee_bps).saturating_sub(referral_amount);
+        if output_b {
+            record_swap_stats(&mut ctx.accounts.pool_stats, 1, (amount_in as u128, output as u128), (fee, 0), now);
+        } else {
+            record_swap_stats(&mut ctx.accounts.pool_stats, 1, (output as u128, amount_in as u128), (0, fee), now);
+        }
+        // event
+        emit!(SwapEvent{message:"swap token".to_string(), operator:ctx.accounts.depositor.key(), input: amount_in, output, referrer, referral_amount });
+        Ok(())
+    }
+
+    // legs are priced one after another, the token movements are netted into at most one transfer per vault
+    pub fn swap_batch(ctx: Context<Swap>, legs: Vec<SwapLeg>) -> Result<Vec<u64>> {
+        require!(!legs.is_empty() && legs.len() <= MAX_BATCH_LEGS, CustError::InvalidBatch);
+        // netted transfers cannot attribute a transfer fee to the legs
+        require!(!(ctx.accounts.pool_account.fee_on_transfer_a || ctx.accounts.pool_account.fee_on_transfer_b), CustError::FeeOnTransferUnsupported);
+        let now = Clock::get()?.unix_timestamp;
+        let (mut reserve_a, mut reserve_b) = (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b);
+        let (mut input_a, mut input_b, mut output_a, mut output_b) = (0u64, 0u64, 0u64, 0u64);
+        let (mut fee_a, mut fee_b) = (0u64, 0u64);
+        let mut outputs: Vec<u64> = Vec::with_capacity(legs.len());
+        // legs move the volatility of a copy, it is only written back once the breaker let the batch through
+        let mut pricing = Pool::clone(&ctx.accounts.pool_account);
+        for leg in legs.iter() {
+            let (fee_bps, volatility) = get_swap_fee_bps(&pricing, now);
+            let before = (reserve_a, reserve_b);
+            let output = if leg.output_b {
+                get_amount_b_out(leg.amount, reserve_a, reserve_b, fee_bps)
+            } else {
+                get_amount_a_out(leg.amount, reserve_a, reserve_b, fee_bps)
+            };
+            require!(output >= leg.min_output, CustError::InsufficientOutputAmount);
+            if leg.output_b {
+                reserve_a = reserve_a.checked_add(leg.amount).ok_or(CustError::MathOverflow)?;
+                reserve_b -= output;
+                input_a += leg.amount;
+                output_b += output;
+                fee_a += get_fee_amount(leg.amount, fee_bps);
+            } else {
+                reserve_b = reserve_b.checked_add(leg.amount).ok_or(CustError::MathOverflow)?;
+                reserve_a -= output;
+                input_b += leg.amount;
+                output_a += output;
+                fee_b += get_fee_amount(leg.amount, fee_bps);
+            }
+            record_swap_volatility(&mut pricing, volatility, before, (reserve_a, reserve_b), now);
+            outputs.push(output);
+        }
+        check_oracle_price(&ctx.accounts.pool_account, &ctx.accounts.oracle, (ctx.accounts.mint_a.decimals, ctx.accounts.mint_b.decimals), (reserve_a, reserve_b), now)?;
+        let start = (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b);
+        if !check_circuit_breaker(&mut ctx.accounts.pool_account, start, (reserve_a, reserve_b), now)? {
+            return Ok(vec![0; legs.len()]);
+        }
+        check_outflow_limit(&mut ctx.accounts.pool_account, start, (reserve_a, reserve_b), now)?;
+        ctx.accounts.pool_account.volatility_accumulator = pricing.volatility_accumulator;
+        ctx.accounts.pool_account.last_trade_ts = pricing.last_trade_ts;
+
+        let authority_bump = ctx.bumps.pool_account;
+        let authority_seeds = &[
+            &ctx.accounts.pool_account.amm.to_bytes(),
+            &ctx.accounts.mint_a.key().to_bytes(),
+            &ctx.accounts.mint_b.key().to_bytes(),
+            POOL_SEED,
+            &[authority_bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        let sides = [
+            (input_a, output_a, &ctx.accounts.depositor_account_a, &ctx.accounts.pool_account_a, &ctx.accounts.mint_a, &ctx.accounts.token_program_a),
+            (input_b, output_b, &ctx.accounts.depositor_account_b, &ctx.accounts.pool_account_b, &ctx.accounts.mint_b, &ctx.accounts.token_program_b),
+        ];
+        for (input, output, depositor_account, pool_account_token, mint, token_program) in sides {
+            if input > output {
+                token_interface::transfer_checked(
+                    CpiContext::new(token_program.to_account_info(),
+                        TransferChecked {
+                            from: depositor_account.to_account_info(),
+                            mint: mint.to_account_info(),
+                            to: pool_account_token.to_account_info(),
+                            authority: ctx.accounts.depositor.to_account_info(),
+                        },
+                    ),
+                    input - output,
+                    mint.decimals,
+                )?;
+            } else if output > input {
+                token_interface::transfer_checked(
+                    CpiContext::new_with_signer(token_program.to_account_info(),
+                        TransferChecked {
+                            from: pool_account_token.to_account_info(),
+                            mint: mint.to_account_info(),
+                            to: depositor_account.to_account_info(),
+                            authority: ctx.accounts.pool_account.to_account_info(),
+                        },
+                        signer_seeds
+                    ),
+                    output - input,
+                    mint.decimals,
+                )?;
+            }
+        }
+
+        (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b) = (reserve_a, reserve_b);
+        check_swap_invariant(&ctx.accounts.pool_account, &mut ctx.accounts.pool_account_a, &mut ctx.accounts.pool_account_b, start)?;
+        let volume = (input_a as u128 + output_a as u128, input_b as u128 + output_b as u128);
+        record_swap_stats(&mut ctx.accounts.pool_stats, legs.len() as u64, volume, (fee_a, fee_b), now);
+        emit!(SwapBatchEvent{message: "swap batch".to_string(), operator: ctx.accounts.depositor.key(), legs: legs.len() as u32, input_a, input_b, output_a, output_b});
+        Ok(outputs)
+    }
+
+    // sends amount_out first, then calls the receiver program which must pay back into the vaults
+    // remaining accounts: [receiver_program, accounts forwarded to the callback ...]
+    pub fn flash_swap<'info>(ctx: Context<'_, '_, 'info, 'info, Swap<'info>>, amount_out: u64, output_b: bool, data: Vec<u8>) -> Result<()> {
+        sync_flagged_reserves(&mut ctx.accounts.pool_account, &ctx.accounts.pool_account_a, &ctx.accounts.pool_account_b);
+        let current_amount_a = ctx.accounts.pool_account.reserve_a;
+        let current_amount_b = ctx.accounts.pool_account.reserve_b;
+        let (out_a, out_b) = if output_b { (0, amount_out) } else { (amount_out, 0) };
+        require!(amount_out > 0, CustError::InsufficientOutputAmount);
+        require!(out_a < current_amount_a && out_b < current_amount_b, CustError::InsufficientLiquidity);
+        let (receiver_program, callback_accounts) = ctx.remaining_accounts.split_first().ok_or(CustError::InvalidReceiverProgram)?;
+        require!(receiver_program.executable, CustError::InvalidReceiverProgram);
+        require_keys_neq!(receiver_program.key(), crate::ID, CustError::InvalidReceiverProgram);
+
+        // transfer output to depositor
+        let authority_bump = ctx.bumps.pool_account;
+        let authority_seeds = &[
+            &ctx.accounts.pool_account.amm.to_bytes(),
+            &ctx.accounts.mint_a.key().to_bytes(),
+            &ctx.accounts.mint_b.key().to_bytes(),
+            POOL_SEED,
+            &[authority_bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        let (pool_account_out, depositor_account_out, mint_out, token_program_out, decimals_out) = if output_b {
+            (ctx.accounts.pool_account_b.to_account_info(), ctx.accounts.depositor_account_b.to_account_info(), ctx.accounts.mint_b.to_account_info(), ctx.accounts.token_program_b.to_account_info(), ctx.accounts.mint_b.decimals)
+        } else {
+            (ctx.accounts.pool_account_a.to_account_info(), ctx.accounts.depositor_account_a.to_account_info(), ctx.accounts.mint_a.to_account_info(), ctx.accounts.token_program_a.to_account_info(), ctx.accounts.mint_a.decimals)
+        };
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(token_program_out,
+                TransferChecked {
+                    from: pool_account_out,
+                    mint: mint_out,
+                    to: depositor_account_out,
+                    authority: ctx.accounts.pool_account.to_account_info(),
+                },
+                signer_seeds
+            ),
+            amount_out,
+            decimals_out,
+        )?;
+
+        // the callback runs without the pool's signature
+        let callback = Instruction {
+            program_id: receiver_program.key(),
+            accounts: callback_accounts.iter().map(|account| AccountMeta {
+                pubkey: account.key(),
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            }).collect(),
+            data,
+        };
+        invoke(&callback, callback_accounts)?;
+
+        // whatever arrived in the vaults on top of the remaining reserves is the input
+        ctx.accounts.pool_account_a.reload()?;
+        ctx.accounts.pool_account_b.reload()?;
+        let balance_a = ctx.accounts.pool_account_a.amount;
+        let balance_b = ctx.accounts.pool_account_b.amount;
+        let in_a = balance_a.saturating_sub(current_amount_a - out_a);
+        let in_b = balance_b.saturating_sub(current_amount_b - out_b);
+        require!(in_a > 0 || in_b > 0, CustError::InsufficientInputAmount);
+
+        let now = Clock::get()?.unix_timestamp;
+        let (fee_bps, volatility) = get_swap_fee_bps(&ctx.accounts.pool_account, now);
+        require!(
+            check_fee_adjusted_k(balance_a, balance_b, in_a, in_b, current_amount_a, current_amount_b, fee_bps),
+            CustError::InvariantViolated
+        );
+        check_oracle_price(&ctx.accounts.pool_account, &ctx.accounts.oracle, (ctx.accounts.mint_a.decimals, ctx.accounts.mint_b.decimals), (balance_a, balance_b), now)?;
+        // tokens already moved, so a trigger can only reject here
+        require!(
+            check_circuit_breaker(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), (balance_a, balance_b), now)?,
+            CustError::CircuitBreakerTriggered
+        );
+        check_outflow_limit(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), (balance_a, balance_b), now)?;
+        record_swap_volatility(&mut ctx.accounts.pool_account, volatility, (current_amount_a, current_amount_b), (balance_a, balance_b), now);
+        (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b) = (balance_a, balance_b);
+        let volume = (in_a as u128 + out_a as u128, in_b as u128 + out_b as u128);
+        let fee = (get_fee_amount(in_a, fee_bps), get_fee_amount(in_b, fee_bps));
+        record_swap_stats(&mut ctx.accounts.pool_stats, 1, volume, fee, now);
+        emit!(SwapEvent{message:"flash swap".to_string(), operator:ctx.accounts.depositor.key(), input: in_a.max(in_b), output: amount_out, referrer: None, referral_amount: 0 });
+        Ok(())
+    }
+
+    // N-asset stable pool, mints are passed in remaining_accounts in pool order and
+    // mints_hash = sha256(mint_0 || mint_1 || ...) makes the pool address unique per mint set
+    pub fn create_multi_pool<'info>(ctx: Context<'_, '_, 'info, 'info, CreateMultiPool<'info>>, mints_hash: [u8; 32], amp: u64, fee_bps: u16) -> Result<()> {
+        let mints = ctx.remaining_accounts;
+        require!(mints.len() >= 2 && mints.len() <= MAX_MULTI_TOKENS, CustError::InvalidTokenCount);
+        require!(amp > 0 && amp <= MAX_AMP, CustError::InvalidAmp);
+        require!(fee_bps <= MAX_FEE_BPS, CustError::InvalidFee);
+
+        let mut keys: Vec<Pubkey> = Vec::with_capacity(mints.len());
+        let mut decimals = None;
+        for mint_info in mints.iter() {
+            let mint = Account::<Mint>::try_from(mint_info)?;
+            require!(!keys.contains(&mint_info.key()), CustError::DuplicateMint);
+            // stable math compares raw amounts, so every token must use the same precision
+            require!(*decimals.get_or_insert(mint.decimals) == mint.decimals, CustError::DecimalsMismatch);
+            keys.push(mint_info.key());
+        }
+        let key_bytes: Vec<&[u8]> = keys.iter().map(|key| key.as_ref()).collect();
+        require!(hashv(&key_bytes).to_bytes() == mints_hash, CustError::MintHashMismatch);
+
+        let multi_pool = &mut ctx.accounts.multi_pool;
+        multi_pool.amm = ctx.accounts.amm.admin;
+        multi_pool.mints_hash = mints_hash;
+        multi_pool.num_tokens = keys.len() as u8;
+        multi_pool.mints[..keys.len()].copy_from_slice(&keys);
+        multi_pool.amp = amp;
+        multi_pool.fee_bps = fee_bps;
+        multi_pool.bump = ctx.bumps.multi_pool;
+        emit!(AMMEvent{message: "multi pool created".to_string(), creator: multi_pool.amm});
+        Ok(())
+    }
+
+    // remaining accounts: [mint_0, vault_0, mint_1, vault_1, ...]
+    pub fn create_multi_pool_token<'info>(ctx: Context<'_, '_, 'info, 'info, CreateMultiPoolToken<'info>>) -> Result<()> {
+        let multi_pool = &ctx.accounts.multi_pool;
+        require!(ctx.remaining_accounts.len() == multi_pool.num_tokens as usize * 2, CustError::InvalidTokenCount);
+        for (i, pair) in ctx.remaining_accounts.chunks(2).enumerate() {
+            require_keys_eq!(pair[0].key(), multi_pool.mints[i], CustError::InvalidMint);
+            // anyone can create a vault ATA ahead of the pool, so an existing one is accepted and checked
+            associated_token::create_idempotent(
+                CpiContext::new(
+                    ctx.accounts.associated_token_program.to_account_info(),
+                    associated_token::Create {
+                        payer: ctx.accounts.payer.to_account_info(),
+                        associated_token: pair[1].clone(),
+                        authority: multi_pool.to_account_info(),
+                        mint: pair[0].clone(),
+                        system_program: ctx.accounts.system_program.to_account_info(),
+                        token_program: ctx.accounts.token_program.to_account_info(),
+                    },
+                ),
+            )?;
+            let vault = Account::<TokenAccount>::try_from(&pair[1])?;
+            require_keys_eq!(vault.mint, multi_pool.mints[i], CustError::InvalidVaultAccount);
+            require_keys_eq!(vault.owner, multi_pool.key(), CustError::InvalidVaultAccount);
+        }
+        Ok(())
+    }
+
+    // remaining accounts: [vault_0 .. vault_n-1, depositor_account_0 .. depositor_account_n-1]
+    pub fn add_liquidity_multi<'info>(ctx: Context<'_, '_, 'info, 'info, AddLiquidityMulti<'info>>, amounts: Vec<u64>, min_lp_out: u64) -> Result<()> {
+        let num_tokens = ctx.accounts.multi_pool.num_tokens as usize;
+        require!(amounts.len() == num_tokens && ctx.remaining_accounts.len() == num_tokens * 2, CustError::InvalidTokenCount);
+        let (vault_infos, depositor_infos) = ctx.remaining_accounts.split_at(num_tokens);
+        let mut vaults = load_multi_vaults(&ctx.accounts.multi_pool, vault_infos)?;
+        let depositor_accounts = load_multi_depositor_accounts(&ctx.accounts.multi_pool, &ctx.accounts.depositor.key(), depositor_infos)?;
+        let balances: Vec<u64> = vaults.iter().map(|vault| vault.amount).collect();
+        let supply = ctx.accounts.mint_liquidity.supply;
+
+        let acctual_amounts: Vec<u64>;
+        let liquidity_to_add: u64;
+        let mut locked_liquidity: u64 = 0;
+        if supply == 0 {
+            // the first deposit sets the price, its liquidity is the invariant D
+            require!(amounts.iter().all(|amount| *amount > 0), CustError::DepositTooSmall);
+            let mut liquidity = stable_get_d(&amounts, ctx.accounts.multi_pool.amp)
+                .and_then(|d| u64::try_from(d).ok())
+                .ok_or(CustError::MathOverflow)?;
+            require!(liquidity > MIN_LIQUIDITY, CustError::DepositTooSmall);
+            liquidity -= MIN_LIQUIDITY;
+            locked_liquidity = MIN_LIQUIDITY;
+            liquidity_to_add = liquidity;
+            acctual_amounts = amounts;
+        }else {
+            require!(balances.iter().all(|balance| *balance > 0), CustError::InvalidVaultAccount);
+            (liquidity_to_add, acctual_amounts) = cacl_liquidity_multi(supply, &amounts, &balances);
+        }
+        require!(liquidity_to_add >= min_lp_out, CustError::InsufficientLiquidityMinted);
+
+        // transfer from depositor's token accounts to the vaults
+        for ((depositor_account, vault), amount) in depositor_accounts.iter().zip(vaults.iter()).zip(acctual_amounts.iter()) {
+            token::transfer(
+                CpiContext::new(ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: depositor_account.to_account_info(),
+                        to: vault.to_account_info(),
+                        authority: ctx.accounts.depositor.to_account_info(),
+                    },
+                ),
+                *amount,
+            )?;
+        }
+
+        // Mint the liquidity to user
+        let authority_seeds = &[
+            ctx.accounts.multi_pool.amm.as_ref(),
+            ctx.accounts.multi_pool.mints_hash.as_ref(),
+            MULTI_POOL_SEED,
+            &[ctx.accounts.multi_pool.bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.mint_liquidity.to_account_info(),
+                    to: ctx.accounts.depositor_account_liquidity.to_account_info(),
+                    authority: ctx.accounts.multi_pool.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            liquidity_to_add,
+        )?;
+        if locked_liquidity > 0 {
+            token::mint_to(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    MintTo {
+                        mint: ctx.accounts.mint_liquidity.to_account_info(),
+                        to: ctx.accounts.pool_account_liquidity.to_account_info(),
+                        authority: ctx.accounts.multi_pool.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                locked_liquidity,
+            )?;
+        }
+        check_multi_liquidity_invariant(&mut vaults, ctx.accounts.multi_pool.amp, &mut ctx.accounts.mint_liquidity, (&balances, supply))?;
+        emit!(MultiLiquidityEvent{message:"add liquidity".to_string(), operator: ctx.accounts.depositor.key(), amounts: acctual_amounts, lp_token: liquidity_to_add});
+        Ok(())
+    }
+
+    // remaining accounts: [vault_0 .. vault_n-1, depositor_account_0 .. depositor_account_n-1]
+    pub fn remove_liquidity_multi<'info>(ctx: Context<'_, '_, 'info, 'info, RemoveLiquidityMulti<'info>>, liquidity: u64, min_amounts: Vec<u64>) -> Result<()> {
+        let num_tokens = ctx.accounts.multi_pool.num_tokens as usize;
+        require!(min_amounts.len() == num_tokens && ctx.remaining_accounts.len() == num_tokens * 2, CustError::InvalidTokenCount);
+        let (vault_infos, depositor_infos) = ctx.remaining_accounts.split_at(num_tokens);
+        let mut vaults = load_multi_vaults(&ctx.accounts.multi_pool, vault_infos)?;
+        let depositor_accounts = load_multi_depositor_accounts(&ctx.accounts.multi_pool, &ctx.accounts.depositor.key(), depositor_infos)?;
+        let balances: Vec<u64> = vaults.iter().map(|vault| vault.amount).collect();
+
+        let supply = ctx.accounts.mint_liquidity.supply;
+        let mut amounts: Vec<u64> = Vec::with_capacity(num_tokens);
+        for (balance, min_amount) in balances.iter().zip(min_amounts.iter()) {
+            let amount = get_token_amount(liquidity, supply, *balance);
+            require!(amount >= *min_amount, CustError::InsufficientOutputAmount);
+            amounts.push(amount);
+        }
+
+        // transfer token from the vaults to depositor accounts
+        let authority_seeds = &[
+            ctx.accounts.multi_pool.amm.as_ref(),
+            ctx.accounts.multi_pool.mints_hash.as_ref(),
+            MULTI_POOL_SEED,
+            &[ctx.accounts.multi_pool.bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        for ((vault, depositor_account), amount) in vaults.iter().zip(depositor_accounts.iter()).zip(amounts.iter()) {
+            token::transfer(
+                CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: vault.to_account_info(),
+                        to: depositor_account.to_account_info(),
+                        authority: ctx.accounts.multi_pool.to_account_info(),
+                    },
+                    signer_seeds
+                ),
+                *amount,
+            )?;
+        }
+
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn{
+                    mint: ctx.accounts.mint_liquidity.to_account_info(),
+                    from: ctx.accounts.depositor_account_liquidity.to_account_info(),
+                    authority:ctx.accounts.depositor.to_account_info()
+                }
+            ),
+            liquidity)?;
+        check_multi_liquidity_invariant(&mut vaults, ctx.accounts.multi_pool.amp, &mut ctx.accounts.mint_liquidity, (&balances, supply))?;
+        emit!(MultiLiquidityEvent{message:"remove liquidity".to_string(), operator: ctx.accounts.depositor.key(), amounts, lp_token: liquidity});
+        Ok(())
+    }
+
+    // remaining accounts: [vault_0 .. vault_n-1], the whole pool is needed to evaluate the invariant
+    pub fn swap_multi<'info>(ctx: Context<'_, '_, 'info, 'info, SwapMulti<'info>>, index_in: u8, index_out: u8, amount: u64, min_output: u64) -> Result<()> {
+        let num_tokens = ctx.accounts.multi_pool.num_tokens as usize;
+        let (index_in, index_out) = (index_in as usize, index_out as usize);
+        require!(ctx.remaining_accounts.len() == num_tokens, CustError::InvalidTokenCount);
+        require!(index_in < num_tokens && index_out < num_tokens && index_in != index_out, CustError::InvalidTokenIndex);
+        require_keys_eq!(ctx.accounts.depositor_account_in.mint, ctx.accounts.multi_pool.mints[index_in], CustError::InvalidDepositorAccount);
+        require_keys_eq!(ctx.accounts.depositor_account_out.mint, ctx.accounts.multi_pool.mints[index_out], CustError::InvalidDepositorAccount);
+        let mut vaults = load_multi_vaults(&ctx.accounts.multi_pool, ctx.remaining_accounts)?;
+        let balances: Vec<u64> = vaults.iter().map(|vault| vault.amount).collect();
+
+        let output = get_stable_amount_out(amount, index_in, index_out, &balances, ctx.accounts.multi_pool.amp, ctx.accounts.multi_pool.fee_bps)
+            .ok_or(CustError::MathOverflow)?;
+        require!(output >= min_output, CustError::InsufficientOutputAmount);
+
+        // transfer input to pool
+        token::transfer(
+            CpiContext::new(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.depositor_account_in.to_account_info(),
+                    to: vaults[index_in].to_account_info(),
+                    authority: ctx.accounts.depositor.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+        // transfer output to depositor
+        let authority_seeds = &[
+            ctx.accounts.multi_pool.amm.as_ref(),
+            ctx.accounts.multi_pool.mints_hash.as_ref(),
+            MULTI_POOL_SEED,
+            &[ctx.accounts.multi_pool.bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: vaults[index_out].to_account_info(),
+                    to: ctx.accounts.depositor_account_out.to_account_info(),
+                    authority: ctx.accounts.multi_pool.to_account_info(),
+                },
+                signer_seeds
+            ),
+            output,
+        )?;
+        check_multi_swap_invariant(&mut vaults, ctx.accounts.multi_pool.amp, &balances)?;
+        emit!(SwapEvent{message:"swap token".to_string(), operator:ctx.accounts.depositor.key(), input: amount, output, referrer: None, referral_amount: 0 });
+        Ok(())
+    }
+
+    // limit order, escrows amount_in + tip until the pool can pay at least min_output
+    pub fn place_order(ctx: Context<PlaceOrder>, order_id: u64, amount_in: u64, min_output: u64, tip: u64) -> Result<()> {
+        require!(amount_in > 0 && min_output > 0, CustError::InvalidOrder);
+        token::transfer(
+            CpiContext::new(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.owner_account_in.to_account_info(),
+                    to: ctx.accounts.escrow.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount_in.checked_add(tip).ok_or(CustError::MathOverflow)?,
+        )?;
+
+        let order = &mut ctx.accounts.order;
+        order.owner = ctx.accounts.owner.key();
+        order.pool = ctx.accounts.pool_account.key();
+        order.input_mint = ctx.accounts.input_mint.key();
+        order.output_mint = ctx.accounts.output_mint.key();
+        order.order_id = order_id;
+        order.amount_in = amount_in;
+        order.min_output = min_output;
+        order.tip = tip;
+        order.bump = ctx.bumps.order;
+        emit!(OrderEvent{message: "order placed".to_string(), owner: order.owner, order: order.key(), amount_in, output: 0});
+        Ok(())
+    }
+
+    pub fn cancel_order(ctx: Context<CancelOrder>) -> Result<()> {
+        let order = &ctx.accounts.order;
+        let authority_seeds = &[
+            order.pool.as_ref(),
+            order.owner.as_ref(),
+            &order.order_id.to_le_bytes(),
+            ORDER_SEED,
+            &[order.bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.escrow.to_account_info(),
+                    to: ctx.accounts.owner_account_in.to_account_info(),
+                    authority: ctx.accounts.order.to_account_info(),
+                },
+                signer_seeds
+            ),
+            ctx.accounts.escrow.amount,
+        )?;
+        token::close_account(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.escrow.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.order.to_account_info(),
+                },
+                signer_seeds
+            ),
+        )?;
+        emit!(OrderEvent{message: "order cancelled".to_string(), owner: order.owner, order: order.key(), amount_in: order.amount_in, output: 0});
+        Ok(())
+    }
+
+    // permissionless, the filler earns the order's tip
+    pub fn fill_order(ctx: Context<FillOrder>) -> Result<()> {
+        let order = &ctx.accounts.order;
+        let sell_a = order.input_mint == ctx.accounts.pool_account.mint_a;
+        let current_amount_a = ctx.accounts.pool_account.reserve_a;
+        let current_amount_b = ctx.accounts.pool_account.reserve_b;
+        let now = Clock::get()?.unix_timestamp;
+        let (fee_bps, volatility) = get_swap_fee_bps(&ctx.accounts.pool_account, now);
+        let output = if sell_a {
+            get_amount_b_out(order.amount_in, current_amount_a, current_amount_b, fee_bps)
+        } else {
+            get_amount_a_out(order.amount_in, current_amount_a, current_amount_b, fee_bps)
+        };
+        require!(output >= order.min_output, CustError::OrderPriceNotReached);
+        let after = if sell_a {
+            (current_amount_a + order.amount_in, current_amount_b - output)
+        } else {
+            (current_amount_a - output, current_amount_b + order.amount_in)
+        };
+        check_oracle_price(&ctx.accounts.pool_account, &ctx.accounts.oracle, (ctx.accounts.mint_a.decimals, ctx.accounts.mint_b.decimals), after, now)?;
+        // an Ok exit would close the order over a funded escrow, so a trigger can only reject here
+        require!(
+            check_circuit_breaker(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), after, now)?,
+            CustError::CircuitBreakerTriggered
+        );
+
+        // escrow pays the pool and the filler
+        let order_seeds = &[
+            order.pool.as_ref(),
+            order.owner.as_ref(),
+            &order.order_id.to_le_bytes(),
+            ORDER_SEED,
+            &[order.bump],
+        ];
+        let order_signer = &[&order_seeds[..]];
+        let (pool_account_in, pool_account_out) = if sell_a {
+            (ctx.accounts.pool_account_a.to_account_info(), ctx.accounts.pool_account_b.to_account_info())
+        } else {
+            (ctx.accounts.pool_account_b.to_account_info(), ctx.accounts.pool_account_a.to_account_info())
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.escrow.to_account_info(),
+                    to: pool_account_in,
+                    authority: ctx.accounts.order.to_account_info(),
+                },
+                order_signer
+            ),
+            order.amount_in,
+        )?;
+        if order.tip > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.escrow.to_account_info(),
+                        to: ctx.accounts.filler_account.to_account_info(),
+                        authority: ctx.accounts.order.to_account_info(),
+                    },
+                    order_signer
+                ),
+                order.tip,
+            )?;
+        }
+        token::close_account(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.escrow.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.order.to_account_info(),
+                },
+                order_signer
+            ),
+        )?;
+
+        // transfer output to the order owner
+        let authority_seeds = &[
+            &ctx.accounts.pool_account.amm.to_bytes(),
+            &ctx.accounts.pool_account.mint_a.to_bytes(),
+            &ctx.accounts.pool_account.mint_b.to_bytes(),
+            POOL_SEED,
+            &[ctx.accounts.pool_account.bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: pool_account_out,
+                    to: ctx.accounts.owner_account_out.to_account_info(),
+                    authority: ctx.accounts.pool_account.to_account_info(),
+                },
+                signer_seeds
+            ),
+            output,
+        )?;
+
+        let (owner, order_key, amount_in) = (order.owner, order.key(), order.amount_in);
+        check_outflow_limit(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), after, now)?;
+        record_swap_volatility(&mut ctx.accounts.pool_account, volatility, (current_amount_a, current_amount_b), after, now);
+        (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b) = after;
+        check_swap_invariant(&ctx.accounts.pool_account, &mut ctx.accounts.pool_account_a, &mut ctx.accounts.pool_account_b, (current_amount_a, current_amount_b))?;
+        let fee = get_fee_amount(amount_in, fee_bps);
+        if sell_a {
+            record_swap_stats(&mut ctx.accounts.pool_stats, 1, (amount_in as u128, output as u128), (fee, 0), now);
+        } else {
+            record_swap_stats(&mut ctx.accounts.pool_stats, 1, (output as u128, amount_in as u128), (0, fee), now);
+        }
+        emit!(OrderEvent{message: "order filled".to_string(), owner, order: order_key, amount_in, output});
+        Ok(())
+    }
+
+    // recurring swap, a keeper executes one slice of the escrowed deposit every interval seconds
+    pub fn create_dca(ctx: Context<CreateDca>, dca_id: u64, amount: u64, slice_amount: u64, interval: i64, min_price: u64, keeper_reward: u64) -> Result<()> {
+        require!(amount > 0 && slice_amount > 0 && interval > 0, CustError::InvalidOrder);
+        require!(keeper_reward < slice_amount, CustError::InvalidOrder);
+        token::transfer(
+            CpiContext::new(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.owner_account_in.to_account_info(),
+                    to: ctx.accounts.escrow.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let dca = &mut ctx.accounts.dca;
+        dca.owner = ctx.accounts.owner.key();
+        dca.pool = ctx.accounts.pool_account.key();
+        dca.input_mint = ctx.accounts.input_mint.key();
+        dca.output_mint = ctx.accounts.output_mint.key();
+        dca.dca_id = dca_id;
+        dca.slice_amount = slice_amount;
+        dca.interval = interval;
+        dca.min_price = min_price;
+        dca.keeper_reward = keeper_reward;
+        dca.last_executed_ts = 0;
+        dca.bump = ctx.bumps.dca;
+        emit!(OrderEvent{message: "dca created".to_string(), owner: dca.owner, order: dca.key(), amount_in: amount, output: 0});
+        Ok(())
+    }
+
+    pub fn cancel_dca(ctx: Context<CancelDca>) -> Result<()> {
+        let dca = &ctx.accounts.dca;
+        let authority_seeds = &[
+            dca.pool.as_ref(),
+            dca.owner.as_ref(),
+            &dca.dca_id.to_le_bytes(),
+            DCA_SEED,
+            &[dca.bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        let remaining = ctx.accounts.escrow.amount;
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.escrow.to_account_info(),
+                    to: ctx.accounts.owner_account_in.to_account_info(),
+                    authority: ctx.accounts.dca.to_account_info(),
+                },
+                signer_seeds
+            ),
+            remaining,
+        )?;
+        token::close_account(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.escrow.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.dca.to_account_info(),
+                },
+                signer_seeds
+            ),
+        )?;
+        emit!(OrderEvent{message: "dca cancelled".to_string(), owner: dca.owner, order: dca.key(), amount_in: remaining, output: 0});
+        Ok(())
+    }
+
+    // permissionless, the keeper reward is taken out of the slice before it is swapped
+    pub fn execute_dca_slice(ctx: Context<ExecuteDcaSlice>) -> Result<()> {
+        let dca = &ctx.accounts.dca;
+        let now = Clock::get()?.unix_timestamp;
+        require!(ctx.accounts.escrow.amount > 0, CustError::DcaCompleted);
+        require!(now >= dca.last_executed_ts + dca.interval, CustError::DcaIntervalNotElapsed);
+        let slice = dca.slice_amount.min(ctx.accounts.escrow.amount);
+        let reward = dca.keeper_reward.min(slice);
+        let amount_in = slice - reward;
+
+        let sell_a = dca.input_mint == ctx.accounts.pool_account.mint_a;
+        let current_amount_a = ctx.accounts.pool_account.reserve_a;
+        let current_amount_b = ctx.accounts.pool_account.reserve_b;
+        let (fee_bps, volatility) = get_swap_fee_bps(&ctx.accounts.pool_account, now);
+        let output = if sell_a {
+            get_amount_b_out(amount_in, current_amount_a, current_amount_b, fee_bps)
+        } else {
+            get_amount_a_out(amount_in, current_amount_a, current_amount_b, fee_bps)
+        };
+        require!(output > 0, CustError::InsufficientOutputAmount);
+        require!(output >= get_min_output(amount_in, dca.min_price), CustError::OrderPriceNotReached);
+        let after = if sell_a {
+            (current_amount_a + amount_in, current_amount_b - output)
+        } else {
+            (current_amount_a - output, current_amount_b + amount_in)
+        };
+        check_oracle_price(&ctx.accounts.pool_account, &ctx.accounts.oracle, (ctx.accounts.mint_a.decimals, ctx.accounts.mint_b.decimals), after, now)?;
+        if !check_circuit_breaker(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), after, now)? {
+            return Ok(());
+        }
+
+        // escrow pays the pool and the keeper
+        let dca_seeds = &[
+            dca.pool.as_ref(),
+            dca.owner.as_ref(),
+            &dca.dca_id.to_le_bytes(),
+            DCA_SEED,
+            &[dca.bump],
+        ];
+        let dca_signer = &[&dca_seeds[..]];
+        let (pool_account_in, pool_account_out) = if sell_a {
+            (ctx.accounts.pool_account_a.to_account_info(), ctx.accounts.pool_account_b.to_account_info())
+        } else {
+            (ctx.accounts.pool_account_b.to_account_info(), ctx.accounts.pool_account_a.to_account_info())
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.escrow.to_account_info(),
+                    to: pool_account_in,
+                    authority: ctx.accounts.dca.to_account_info(),
+                },
+                dca_signer
+            ),
+            amount_in,
+        )?;
+        if reward > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.escrow.to_account_info(),
+                        to: ctx.accounts.keeper_account.to_account_info(),
+                        authority: ctx.accounts.dca.to_account_info(),
+                    },
+                    dca_signer
+                ),
+                reward,
+            )?;
+        }
+
+        // transfer output to the dca owner
+        let authority_seeds = &[
+            &ctx.accounts.pool_account.amm.to_bytes(),
+            &ctx.accounts.pool_account.mint_a.to_bytes(),
+            &ctx.accounts.pool_account.mint_b.to_bytes(),
+            POOL_SEED,
+            &[ctx.accounts.pool_account.bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: pool_account_out,
+                    to: ctx.accounts.owner_account_out.to_account_info(),
+                    authority: ctx.accounts.pool_account.to_account_info(),
+                },
+                signer_seeds
+            ),
+            output,
+        )?;
+
+        let (owner, dca_key) = (dca.owner, dca.key());
+        ctx.accounts.dca.last_executed_ts = now;
+        check_outflow_limit(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), after, now)?;
+        record_swap_volatility(&mut ctx.accounts.pool_account, volatility, (current_amount_a, current_amount_b), after, now);
+        (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b) = after;
+        check_swap_invariant(&ctx.accounts.pool_account, &mut ctx.accounts.pool_account_a, &mut ctx.accounts.pool_account_b, (current_amount_a, current_amount_b))?;
+        let fee = get_fee_amount(amount_in, fee_bps);
+        if sell_a {
+            record_swap_stats(&mut ctx.accounts.pool_stats, 1, (amount_in as u128, output as u128), (fee, 0), now);
+        } else {
+            record_swap_stats(&mut ctx.accounts.pool_stats, 1, (output as u128, amount_in as u128), (0, fee), now);
+        }
+        emit!(OrderEvent{message: "dca slice executed".to_string(), owner, order: dca_key, amount_in, output});
+        Ok(())
+    }
+
+    // breaker_bps = 0 disables the breaker, setting it also unpauses the pool and restarts the TWAP at spot
+    pub fn set_circuit_breaker(ctx: Context<UpdatePool>, breaker_bps: u16, twap_window: i64, pause_on_trigger: bool) -> Result<()> {
+        require!(breaker_bps == 0 || twap_window > 0, CustError::InvalidCircuitBreaker);
+        let pool_account = &mut ctx.accounts.pool_account;
+        pool_account.breaker_bps = breaker_bps;
+        pool_account.twap_window = twap_window;
+        pool_account.breaker_pause = pause_on_trigger;
+        pool_account.paused = false;
+        pool_account.twap_price = get_spot_price(pool_account.reserve_a, pool_account.reserve_b);
+        pool_account.twap_last_ts = Clock::get()?.unix_timestamp;
+        emit!(AMMEvent{message: "circuit breaker updated".to_string(), creator: pool_account.amm});
+        Ok(())
+    }
+
+    // oracle = Pubkey::default() disables the oracle check
+    pub fn set_oracle(ctx: Context<UpdatePool>, oracle: Pubkey, max_staleness: i64, max_conf_bps: u16, max_deviation_bps: u16) -> Result<()> {
+        require!(max_staleness > 0, CustError::InvalidOracle);
+        let pool_account = &mut ctx.accounts.pool_account;
+        pool_account.oracle = oracle;
+        pool_account.oracle_max_staleness = max_staleness;
+        pool_account.oracle_max_conf_bps = max_conf_bps;
+        pool_account.oracle_max_deviation_bps = max_deviation_bps;
+        emit!(AMMEvent{message: "oracle updated".to_string(), creator: pool_account.amm});
+        Ok(())
+    }
+
+    // limit_bps of the reserves may leave the pool per window, 0 disables the limit
+    pub fn set_outflow_limit(ctx: Context<UpdatePool>, limit_bps: u16, window: i64) -> Result<()> {
+        require!(limit_bps as u64 <= BPS_DENOMINATOR, CustError::InvalidOutflowLimit);
+        require!(limit_bps == 0 || window > 0, CustError::InvalidOutflowLimit);
+        let pool_account = &mut ctx.accounts.pool_account;
+        pool_account.outflow_limit_bps = limit_bps;
+        pool_account.outflow_window = window;
+        pool_account.outflow_a = 0;
+        pool_account.outflow_b = 0;
+        emit!(AMMEvent{message: "outflow limit updated".to_string(), creator: pool_account.amm});
+        Ok(())
+    }
+
+    // 0 keeps withdrawals instant
+    pub fn set_withdraw_cooldown(ctx: Context<UpdatePool>, cooldown: i64) -> Result<()> {
+        require!(cooldown >= 0, CustError::InvalidWithdrawCooldown);
+        let pool_account = &mut ctx.accounts.pool_account;
+        pool_account.withdraw_cooldown = cooldown;
+        emit!(AMMEvent{message: "withdraw cooldown updated".to_string(), creator: pool_account.amm});
+        Ok(())
+    }
+
+    // Swap, AddLiquidity and RemoveLiquidity then require the depositor's allowlist entry,
+    // orders, DCAs, commits and batches placed before can no longer execute, only refund
+    pub fn set_permissioned(ctx: Context<UpdatePool>, permissioned: bool) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        pool_account.permissioned = permissioned;
+        emit!(AMMEvent{message: "pool permission updated".to_string(), creator: pool_account.amm});
+        Ok(())
+    }
+
+    pub fn add_to_allowlist(ctx: Context<AddToAllowlist>, user: Pubkey) -> Result<()> {
+        let allowlist = &mut ctx.accounts.allowlist;
+        allowlist.pool = ctx.accounts.pool_account.key();
+        allowlist.user = user;
+        allowlist.bump = ctx.bumps.allowlist;
+        emit!(AllowlistEvent{message: "allowlist added".to_string(), pool: allowlist.pool, user});
+        Ok(())
+    }
+
+    pub fn remove_from_allowlist(ctx: Context<RemoveFromAllowlist>) -> Result<()> {
+        let allowlist = &ctx.accounts.allowlist;
+        emit!(AllowlistEvent{message: "allowlist removed".to_string(), pool: allowlist.pool, user: allowlist.user});
+        Ok(())
+    }
+
+    pub fn set_batch_auction(ctx: Context<UpdatePool>, enabled: bool) -> Result<()> {
+        let pool_account = &mut ctx.accounts.pool_account;
+        pool_account.batch_auction = enabled;
+        emit!(AMMEvent{message: "batch auction updated".to_string(), creator: pool_account.amm});
+        Ok(())
+    }
+
+    // queues a swap intent into the batch of the current slot, the input is escrowed by the batch
+    pub fn queue_swap(ctx: Context<QueueSwap>, slot: u64, amount_in: u64, sell_a: bool) -> Result<()> {
+        require!(slot == Clock::get()?.slot, CustError::InvalidBatchSlot);
+        require!(amount_in > 0, CustError::InvalidOrder);
+        let batch_account_in = if sell_a { &ctx.accounts.batch_account_a } else { &ctx.accounts.batch_account_b };
+        token::transfer(
+            CpiContext::new(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.owner_account_in.to_account_info(),
+                    to: batch_account_in.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount_in,
+        )?;
+
+        let batch = &mut ctx.accounts.batch;
+        if batch.pool == Pubkey::default() {
+            batch.pool = ctx.accounts.pool_account.key();
+            batch.slot = slot;
+            batch.bump = ctx.bumps.batch;
+        }
+        if sell_a {
+            batch.total_in_a = batch.total_in_a.checked_add(amount_in).ok_or(CustError::MathOverflow)?;
+        } else {
+            batch.total_in_b = batch.total_in_b.checked_add(amount_in).ok_or(CustError::MathOverflow)?;
+        }
+
+        let intent = &mut ctx.accounts.intent;
+        intent.owner = ctx.accounts.owner.key();
+        intent.batch = batch.key();
+        intent.amount_in = amount_in;
+        intent.sell_a = sell_a;
+        intent.bump = ctx.bumps.intent;
+        emit!(OrderEvent{message: "swap queued".to_string(), owner: intent.owner, order: intent.key(), amount_in, output: 0});
+        Ok(())
+    }
+
+    // permissionless once the slot is over, every intent of the batch clears at one price
+    pub fn settle_batch(ctx: Context<SettleBatch>) -> Result<()> {
+        let batch = &ctx.accounts.batch;
+        require!(Clock::get()?.slot > batch.slot, CustError::BatchNotClosed);
+        let (total_in_a, total_in_b) = (batch.total_in_a, batch.total_in_b);
+        let current_amount_a = ctx.accounts.pool_account.reserve_a;
+        let current_amount_b = ctx.accounts.pool_account.reserve_b;
+        let now = Clock::get()?.unix_timestamp;
+        let (fee_bps, volatility) = get_swap_fee_bps(&ctx.accounts.pool_account, now);
+
+        // the side selling more than the other side buys at spot trades the imbalance with the pool
+        let sell_a = total_in_a as u128 * current_amount_b as u128 >= total_in_b as u128 * current_amount_a as u128;
+        let (pool_in, pool_out) = if sell_a {
+            get_batch_clearing(total_in_a, total_in_b, current_amount_a, current_amount_b, fee_bps)
+        } else {
+            get_batch_clearing(total_in_b, total_in_a, current_amount_b, current_amount_a, fee_bps)
+        };
+        let (payout_a, payout_b, after) = if sell_a {
+            (total_in_a - pool_in, total_in_b + pool_out, (current_amount_a + pool_in, current_amount_b - pool_out))
+        } else {
+            (total_in_a + pool_out, total_in_b - pool_in, (current_amount_a - pool_out, current_amount_b + pool_in))
+        };
+        check_oracle_price(&ctx.accounts.pool_account, &ctx.accounts.oracle, (ctx.accounts.mint_a.decimals, ctx.accounts.mint_b.decimals), after, now)?;
+        if !check_circuit_breaker(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), after, now)? {
+            return Ok(());
+        }
+
+        let pool_key = ctx.accounts.pool_account.key();
+        let batch_seeds = &[
+            pool_key.as_ref(),
+            &batch.slot.to_le_bytes(),
+            BATCH_SEED,
+            &[batch.bump],
+        ];
+        let batch_signer = &[&batch_seeds[..]];
+        let authority_seeds = &[
+            &ctx.accounts.pool_account.amm.to_bytes(),
+            &ctx.accounts.pool_account.mint_a.to_bytes(),
+            &ctx.accounts.pool_account.mint_b.to_bytes(),
+            POOL_SEED,
+            &[ctx.accounts.pool_account.bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        let (batch_account_in, pool_account_in, pool_account_out, batch_account_out) = if sell_a {
+            (&ctx.accounts.batch_account_a, &ctx.accounts.pool_account_a, &ctx.accounts.pool_account_b, &ctx.accounts.batch_account_b)
+        } else {
+            (&ctx.accounts.batch_account_b, &ctx.accounts.pool_account_b, &ctx.accounts.pool_account_a, &ctx.accounts.batch_account_a)
+        };
+        if pool_in > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: batch_account_in.to_account_info(),
+                        to: pool_account_in.to_account_info(),
+                        authority: ctx.accounts.batch.to_account_info(),
+                    },
+                    batch_signer
+                ),
+                pool_in,
+            )?;
+        }
+        if pool_out > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: pool_account_out.to_account_info(),
+                        to: batch_account_out.to_account_info(),
+                        authority: ctx.accounts.pool_account.to_account_info(),
+                    },
+                    signer_seeds
+                ),
+                pool_out,
+            )?;
+        }
+
+        check_outflow_limit(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), after, now)?;
+        record_swap_volatility(&mut ctx.accounts.pool_account, volatility, (current_amount_a, current_amount_b), after, now);
+        (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b) = after;
+        check_swap_invariant(&ctx.accounts.pool_account, &mut ctx.accounts.pool_account_a, &mut ctx.accounts.pool_account_b, (current_amount_a, current_amount_b))?;
+        // only the imbalance traded with the pool
+        if pool_in > 0 {
+            let fee = get_fee_amount(pool_in, fee_bps);
+            if sell_a {
+                record_swap_stats(&mut ctx.accounts.pool_stats, 1, (pool_in as u128, pool_out as u128), (fee, 0), now);
+            } else {
+                record_swap_stats(&mut ctx.accounts.pool_stats, 1, (pool_out as u128, pool_in as u128), (0, fee), now);
+            }
+        }
+        let batch = &mut ctx.accounts.batch;
+        batch.settled = true;
+        batch.payout_a = payout_a;
+        batch.payout_b = payout_b;
+        emit!(BatchEvent{message: "batch settled".to_string(), batch: batch.key(), total_in_a, total_in_b, payout_a, payout_b});
+        Ok(())
+    }
+
+    // pays the intent its pro-rata share of the opposite side's payout
+    pub fn claim_batch(ctx: Context<ClaimBatch>) -> Result<()> {
+        let batch = &ctx.accounts.batch;
+        let intent = &ctx.accounts.intent;
+        let (total_in, payout, batch_account_out) = if intent.sell_a {
+            (batch.total_in_a, batch.payout_b, &ctx.accounts.batch_account_b)
+        } else {
+            (batch.total_in_b, batch.payout_a, &ctx.accounts.batch_account_a)
+        };
+        let output = (intent.amount_in as u128 * payout as u128 / total_in as u128) as u64;
+
+        let batch_seeds = &[
+            batch.pool.as_ref(),
+            &batch.slot.to_le_bytes(),
+            BATCH_SEED,
+            &[batch.bump],
+        ];
+        let batch_signer = &[&batch_seeds[..]];
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: batch_account_out.to_account_info(),
+                    to: ctx.accounts.owner_account_out.to_account_info(),
+                    authority: ctx.accounts.batch.to_account_info(),
+                },
+                batch_signer
+            ),
+            output,
+        )?;
+        emit!(OrderEvent{message: "batch claimed".to_string(), owner: intent.owner, order: intent.key(), amount_in: intent.amount_in, output});
+        Ok(())
+    }
+
+    // returns the escrowed input of a batch that was never settled, the batch totals shrink with it
+    pub fn refund_intent(ctx: Context<RefundIntent>) -> Result<()> {
+        let batch = &ctx.accounts.batch;
+        let intent = &ctx.accounts.intent;
+        require!(Clock::get()?.slot > batch.slot + BATCH_EXPIRY_SLOTS, CustError::BatchNotExpired);
+        let batch_account_in = if intent.sell_a { &ctx.accounts.batch_account_a } else { &ctx.accounts.batch_account_b };
+        let batch_seeds = &[
+            batch.pool.as_ref(),
+            &batch.slot.to_le_bytes(),
+            BATCH_SEED,
+            &[batch.bump],
+        ];
+        let batch_signer = &[&batch_seeds[..]];
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: batch_account_in.to_account_info(),
+                    to: ctx.accounts.owner_account_in.to_account_info(),
+                    authority: ctx.accounts.batch.to_account_info(),
+                },
+                batch_signer
+            ),
+            intent.amount_in,
+        )?;
+
+        let (owner, intent_key, amount_in, sell_a) = (intent.owner, intent.key(), intent.amount_in, intent.sell_a);
+        let batch = &mut ctx.accounts.batch;
+        if sell_a {
+            batch.total_in_a -= amount_in;
+        } else {
+            batch.total_in_b -= amount_in;
+        }
+        emit!(OrderEvent{message: "batch intent refunded".to_string(), owner, order: intent_key, amount_in, output: 0});
+        Ok(())
+    }
+
+    // escrows the input, commitment = hash(min_output, salt); the direction follows the public input mint
+    pub fn commit_swap(ctx: Context<CommitSwap>, commit_id: u64, amount_in: u64, commitment: [u8; 32]) -> Result<()> {
+        require!(amount_in > 0, CustError::InvalidOrder);
+        token::transfer(
+            CpiContext::new(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.owner_account_in.to_account_info(),
+                    to: ctx.accounts.escrow.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            amount_in,
+        )?;
+
+        let commit = &mut ctx.accounts.commit;
+        commit.owner = ctx.accounts.owner.key();
+        commit.pool = ctx.accounts.pool_account.key();
+        commit.input_mint = ctx.accounts.input_mint.key();
+        commit.commit_id = commit_id;
+        commit.amount_in = amount_in;
+        commit.commitment = commitment;
+        commit.slot = Clock::get()?.slot;
+        commit.bump = ctx.bumps.commit;
+        emit!(OrderEvent{message: "swap committed".to_string(), owner: commit.owner, order: commit.key(), amount_in, output: 0});
+        Ok(())
+    }
+
+    // priced like swap_extacttoken_fortoken at reveal time
+    pub fn reveal_swap(ctx: Context<RevealSwap>, min_output: u64, salt: [u8; 32]) -> Result<()> {
+        let commit = &ctx.accounts.commit;
+        let slot = Clock::get()?.slot;
+        require!(slot >= commit.slot + COMMIT_MIN_SLOTS, CustError::CommitTooEarly);
+        require!(slot <= commit.slot + COMMIT_EXPIRY_SLOTS, CustError::CommitExpired);
+        let commitment = hashv(&[&min_output.to_le_bytes(), &salt]).to_bytes();
+        require!(commitment == commit.commitment, CustError::InvalidCommitment);
+        let output_b = commit.input_mint == ctx.accounts.pool_account.mint_a;
+
+        let current_amount_a = ctx.accounts.pool_account.reserve_a;
+        let current_amount_b = ctx.accounts.pool_account.reserve_b;
+        let now = Clock::get()?.unix_timestamp;
+        let (fee_bps, volatility) = get_swap_fee_bps(&ctx.accounts.pool_account, now);
+        let amount_in = commit.amount_in;
+        let output = if output_b {
+            get_amount_b_out(amount_in, current_amount_a, current_amount_b, fee_bps)
+        } else {
+            get_amount_a_out(amount_in, current_amount_a, current_amount_b, fee_bps)
+        };
+        require!(output >= min_output, CustError::InsufficientOutputAmount);
+        let after = if output_b {
+            (current_amount_a + amount_in, current_amount_b - output)
+        } else {
+            (current_amount_a - output, current_amount_b + amount_in)
+        };
+        check_oracle_price(&ctx.accounts.pool_account, &ctx.accounts.oracle, (ctx.accounts.mint_a.decimals, ctx.accounts.mint_b.decimals), after, now)?;
+        // the commit is closed on Ok, a trigger rejects so the escrow stays refundable
+        require!(
+            check_circuit_breaker(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), after, now)?,
+            CustError::CircuitBreakerTriggered
+        );
+
+        let commit_seeds = &[
+            commit.pool.as_ref(),
+            commit.owner.as_ref(),
+            &commit.commit_id.to_le_bytes(),
+            COMMIT_SEED,
+            &[commit.bump],
+        ];
+        let commit_signer = &[&commit_seeds[..]];
+        let (pool_account_in, pool_account_out) = if output_b {
+            (ctx.accounts.pool_account_a.to_account_info(), ctx.accounts.pool_account_b.to_account_info())
+        } else {
+            (ctx.accounts.pool_account_b.to_account_info(), ctx.accounts.pool_account_a.to_account_info())
+        };
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.escrow.to_account_info(),
+                    to: pool_account_in,
+                    authority: ctx.accounts.commit.to_account_info(),
+                },
+                commit_signer
+            ),
+            amount_in,
+        )?;
+        token::close_account(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.escrow.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.commit.to_account_info(),
+                },
+                commit_signer
+            ),
+        )?;
+
+        let authority_seeds = &[
+            &ctx.accounts.pool_account.amm.to_bytes(),
+            &ctx.accounts.pool_account.mint_a.to_bytes(),
+            &ctx.accounts.pool_account.mint_b.to_bytes(),
+            POOL_SEED,
+            &[ctx.accounts.pool_account.bump],
+        ];
+        let signer_seeds = &[&authority_seeds[..]];
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: pool_account_out,
+                    to: ctx.accounts.owner_account_out.to_account_info(),
+                    authority: ctx.accounts.pool_account.to_account_info(),
+                },
+                signer_seeds
+            ),
+            output,
+        )?;
+
+        check_outflow_limit(&mut ctx.accounts.pool_account, (current_amount_a, current_amount_b), after, now)?;
+        record_swap_volatility(&mut ctx.accounts.pool_account, volatility, (current_amount_a, current_amount_b), after, now);
+        (ctx.accounts.pool_account.reserve_a, ctx.accounts.pool_account.reserve_b) = after;
+        check_swap_invariant(&ctx.accounts.pool_account, &mut ctx.accounts.pool_account_a, &mut ctx.accounts.pool_account_b, (current_amount_a, current_amount_b))?;
+        let fee = get_fee_amount(amount_in, fee_bps);
+        if output_b {
+            record_swap_stats(&mut ctx.accounts.pool_stats, 1, (amount_in as u128, output as u128), (fee, 0), now);
+        } else {
+            record_swap_stats(&mut ctx.accounts.pool_stats, 1, (output as u128, amount_in as u128), (0, fee), now);
+        }
+        emit!(SwapEvent{message: "swap revealed".to_string(), operator: ctx.accounts.owner.key(), input: amount_in, output, referrer: None, referral_amount: 0});
+        Ok(())
+    }
+
+    // only once the reveal window is over, so a refund can not be used to back out of a bad price
+    pub fn refund_commit(ctx: Context<RefundCommit>) -> Result<()> {
+        let commit = &ctx.accounts.commit;
+        require!(Clock::get()?.slot > commit.slot + COMMIT_EXPIRY_SLOTS, CustError::CommitNotExpired);
+        let commit_seeds = &[
+            commit.pool.as_ref(),
+            commit.owner.as_ref(),
+            &commit.commit_id.to_le_bytes(),
+            COMMIT_SEED,
+            &[commit.bump],
+        ];
+        let commit_signer = &[&commit_seeds[..]];
+        token::transfer(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.escrow.to_account_info(),
+                    to: ctx.accounts.owner_account_in.to_account_info(),
+                    authority: ctx.accounts.commit.to_account_info(),
+                },
+                commit_signer
+            ),
+            ctx.accounts.escrow.amount,
+        )?;
+        token::close_account(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.escrow.to_account_info(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.commit.to_account_info(),
+                },
+                commit_signer
+            ),
+        )?;
+        emit!(OrderEvent{message: "commit refunded".to_string(), owner: commit.owner, order: commit.key(), amount_in: commit.amount_in, output: 0});
+        Ok(())
+    }
+}
+
+// dynamic fee is priced from the volatility left over from previous trades
+fn get_swap_fee_bps(pool_account: &Pool, now: i64) -> (u16, u64) {
+    if !pool_account.dynamic_fee {
+        return (DEFAULT_FEE_BPS, 0);
+    }
+    let volatility = decay_linear(pool_account.volatility_accumulator, now - pool_account.last_trade_ts, pool_account.decay_period);
+    let fee_bps = get_dynamic_fee_bps(pool_account.base_fee_bps, pool_account.max_fee_bps, pool_account.variable_fee_control, volatility);
+    (fee_bps, volatility)
+}
+
+// reserves are (amount_a, amount_b) before and after the trade
+fn record_swap_volatility(pool_account: &mut Pool, volatility: u64, before: (u64, u64), after: (u64, u64), now: i64) {
+    if !pool_account.dynamic_fee {
+        return;
+    }
+    let price_move = get_price_move_bps(before.0, before.1, after.0, after.1);
+    pool_account.volatility_accumulator = volatility.saturating_add(price_move).min(MAX_VOLATILITY_ACCUMULATOR);
+    pool_account.last_trade_ts = now;
+}
+
+// moves the TWAP towards the pre-trade spot price, then checks the post-trade price against it.
+// Ok(false) means the pool was paused and the trade must be skipped
+// resizes an account of this program to `space` and tops up its rent, shorter data is zero filled
+fn realloc_account<'info>(account: &AccountInfo<'info>, discriminator: &[u8], space: usize, payer: &Signer<'info>, system_program: &Program<'info, System>) -> Result<()> {
+    require!(account.try_borrow_data()?.starts_with(discriminator), ErrorCode::AccountDiscriminatorMismatch);
+    if account.data_len() >= space {
+        return Ok(());
+    }
+    let lamports = Rent::get()?.minimum_balance(space).saturating_sub(account.lamports());
+    if lamports > 0 {
+        system_program::transfer(
+            CpiContext::new(system_program.to_account_info(),
+                system_program::Transfer {
+                    from: payer.to_account_info(),
+                    to: account.clone(),
+                },
+            ),
+            lamports,
+        )?;
+    }
+    account.realloc(space, true)?;
+    Ok(())
+}
+
+// a Token-2022 mint with a transfer fee, the pool has to flag it
+fn has_transfer_fee(mint: &InterfaceAccount<token_interface::Mint>) -> Result<bool> {
+    let info = mint.to_account_info();
+    if *info.owner != spl_token_2022::ID {
+        return Ok(false);
+    }
+    let data = info.try_borrow_data()?;
+    let mint = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    Ok(mint.get_extension::<TransferFeeConfig>().is_ok())
+}
+
+// flagged pools price off the vault balances, whatever a vault gained outside the program is absorbed first
+fn sync_flagged_reserves(pool_account: &mut Pool, vault_a: &InterfaceAccount<token_interface::TokenAccount>, vault_b: &InterfaceAccount<token_interface::TokenAccount>) {
+    if pool_account.fee_on_transfer_a {
+        pool_account.reserve_a = vault_a.amount;
+    }
+    if pool_account.fee_on_transfer_b {
+        pool_account.reserve_b = vault_b.amount;
+    }
+}
+
+// amount that actually reached the vault for a flagged mint, the cached balance must predate the transfer
+fn get_received_amount(fee_on_transfer: bool, vault: &mut InterfaceAccount<token_interface::TokenAccount>, amount: u64) -> Result<u64> {
+    if !fee_on_transfer {
+        return Ok(amount);
+    }
+    let balance_before = vault.amount;
+    vault.reload()?;
+    Ok(vault.amount.saturating_sub(balance_before))
+}
+
+fn check_circuit_breaker(pool_account: &mut Account<Pool>, before: (u64, u64), after: (u64, u64), now: i64) -> Result<bool> {
+    let twap_price = update_twap(pool_account.twap_price, get_spot_price(before.0, before.1), now - pool_account.twap_last_ts, pool_account.twap_window);
+    pool_account.twap_price = twap_price;
+    pool_account.twap_last_ts = now;
+    if pool_account.breaker_bps == 0 || twap_price == 0 {
+        return Ok(true);
+    }
+    let spot_price = get_spot_price(after.0, after.1);
+    let deviation_bps = spot_price.abs_diff(twap_price) as u128 * BPS_DENOMINATOR as u128 / twap_price as u128;
+    if deviation_bps <= pool_account.breaker_bps as u128 {
+        return Ok(true);
+    }
+    let paused = pool_account.breaker_pause;
+    emit!(CircuitBreakerEvent{message: "circuit breaker triggered".to_string(), pool: pool_account.key(), twap_price, spot_price, deviation_bps: deviation_bps.min(u64::MAX as u128) as u64, paused});
+    require!(paused, CustError::CircuitBreakerTriggered);
+    pool_account.paused = true;
+    Ok(false)
+}
+
+// shared post-instruction checks, the vaults are reloaded so a bad transfer or a math bug in utils fails closed.
+// reserves must stay covered by the vaults and k = reserve_a * reserve_b must not decrease on a swap
+fn check_swap_invariant(pool_account: &Pool, vault_a: &mut InterfaceAccount<token_interface::TokenAccount>, vault_b: &mut InterfaceAccount<token_interface::TokenAccount>, before: (u64, u64)) -> Result<()> {
+    check_vaults_cover_reserves(pool_account, vault_a, vault_b)?;
+    let k_before = before.0 as u128 * before.1 as u128;
+    let k_after = pool_account.reserve_a as u128 * pool_account.reserve_b as u128;
+    require!(k_after >= k_before, CustError::InvariantViolated);
+    Ok(())
+}
+
+// LP value per share, sqrt(reserve_a * reserve_b) / supply, must not decrease on a deposit or withdrawal
+fn check_liquidity_invariant(pool_account: &Pool, vault_a: &mut InterfaceAccount<token_interface::TokenAccount>, vault_b: &mut InterfaceAccount<token_interface::TokenAccount>, mint_liquidity: &mut Account<Mint>, before: (u64, u64, u64)) -> Result<()> {
+    check_vaults_cover_reserves(pool_account, vault_a, vault_b)?;
+    mint_liquidity.reload()?;
+    let after = (pool_account.reserve_a, pool_account.reserve_b, mint_liquidity.supply);
+    require!(check_lp_value(before, after), CustError::InvariantViolated);
+    Ok(())
+}
+
+fn check_vaults_cover_reserves(pool_account: &Pool, vault_a: &mut InterfaceAccount<token_interface::TokenAccount>, vault_b: &mut InterfaceAccount<token_interface::TokenAccount>) -> Result<()> {
+    vault_a.reload()?;
+    vault_b.reload()?;
+    require!(vault_a.amount >= pool_account.reserve_a && vault_b.amount >= pool_account.reserve_b, CustError::InvariantViolated);
+    Ok(())
+}
+
+// volume counts both the input and the output side, fees are the LP share in the input token
+fn record_swap_stats(pool_stats: &mut PoolStats, swaps: u64, volume: (u128, u128), fee: (u64, u64), now: i64) {
+    pool_stats.volume_a = pool_stats.volume_a.saturating_add(volume.0);
+    pool_stats.volume_b = pool_stats.volume_b.saturating_add(volume.1);
+    pool_stats.fees_a = pool_stats.fees_a.saturating_add(fee.0 as u128);
+    pool_stats.fees_b = pool_stats.fees_b.saturating_add(fee.1 as u128);
+    pool_stats.swap_count += swaps;
+    pool_stats.last_trade_ts = now;
+}
+
+// outflow is the drop of each reserve, accumulated per token and fading linearly over outflow_window
+fn check_outflow_limit(pool_account: &mut Pool, before: (u64, u64), after: (u64, u64), now: i64) -> Result<()> {
+    if pool_account.outflow_limit_bps == 0 {
+        return Ok(());
+    }
+    let elapsed = now - pool_account.outflow_last_ts;
+    let outflow_a = decay_linear(pool_account.outflow_a, elapsed, pool_account.outflow_window)
+        .saturating_add(before.0.saturating_sub(after.0));
+    let outflow_b = decay_linear(pool_account.outflow_b, elapsed, pool_account.outflow_window)
+        .saturating_add(before.1.saturating_sub(after.1));
+    let limit_a = before.0 as u128 * pool_account.outflow_limit_bps as u128 / BPS_DENOMINATOR as u128;
+    let limit_b = before.1 as u128 * pool_account.outflow_limit_bps as u128 / BPS_DENOMINATOR as u128;
+    require!(outflow_a as u128 <= limit_a && outflow_b as u128 <= limit_b, CustError::OutflowLimitExceeded);
+    pool_account.outflow_a = outflow_a;
+    pool_account.outflow_b = outflow_b;
+    pool_account.outflow_last_ts = now;
+    Ok(())
+}
+
+// post-trade spot price against the pool's oracle, reserves are (amount_a, amount_b)
+// decimals are (mint_a, mint_b)
+fn check_oracle_price(pool_account: &Pool, oracle: &Option<UncheckedAccount>, decimals: (u8, u8), after: (u64, u64), now: i64) -> Result<()> {
+    if pool_account.oracle == Pubkey::default() {
+        return Ok(());
+    }
+    let oracle = oracle.as_ref().ok_or(CustError::InvalidOracle)?;
+    let oracle_price = load_pyth_price(&oracle.try_borrow_data()?)?;
+    require!(now - oracle_price.publish_time <= pool_account.oracle_max_staleness, CustError::OracleStale);
+    let conf_bps = oracle_price.conf as u128 * BPS_DENOMINATOR as u128 / oracle_price.price as u128;
+    require!(conf_bps <= pool_account.oracle_max_conf_bps as u128, CustError::OracleConfidenceTooWide);
+    let reference = get_oracle_price(oracle_price.price, oracle_price.expo, decimals.0, decimals.1)
+        .ok_or(CustError::InvalidOracle)?;
+    require!(reference > 0, CustError::InvalidOracle);
+    let spot_price = get_spot_price(after.0, after.1);
+    let deviation_bps = spot_price.abs_diff(reference) as u128 * BPS_DENOMINATOR as u128 / reference as u128;
+    require!(deviation_bps <= pool_account.oracle_max_deviation_bps as u128, CustError::OraclePriceDeviation);
+    Ok(())
+}
+
+// StableSwap D over the vault balances must not drop across a swap
+fn check_multi_swap_invariant(vaults: &mut [Account<TokenAccount>], amp: u64, before: &[u64]) -> Result<()> {
+    let d_before = stable_get_d(before, amp).ok_or(CustError::MathOverflow)?;
+    let d_after = stable_get_d(&reload_multi_balances(vaults)?, amp).ok_or(CustError::MathOverflow)?;
+    require!(d_after >= d_before, CustError::InvariantViolated);
+    Ok(())
+}
+
+// D per LP share must not drop on a deposit or withdrawal, before is (balances, supply)
+fn check_multi_liquidity_invariant(vaults: &mut [Account<TokenAccount>], amp: u64, mint_liquidity: &mut Account<Mint>, before: (&[u64], u64)) -> Result<()> {
+    let d_before = stable_get_d(before.0, amp).ok_or(CustError::MathOverflow)?;
+    let d_after = stable_get_d(&reload_multi_balances(vaults)?, amp).ok_or(CustError::MathOverflow)?;
+    mint_liquidity.reload()?;
+    require!(check_stable_lp_value((d_before, before.1), (d_after, mint_liquidity.supply)), CustError::InvariantViolated);
+    Ok(())
+}
+
+fn reload_multi_balances(vaults: &mut [Account<TokenAccount>]) -> Result<Vec<u64>> {
+    vaults.iter_mut().map(|vault| {
+        vault.reload()?;
+        Ok(vault.amount)
+    }).collect()
+}
+
+// vaults must be the pool's ATAs, in the same order as MultiPool.mints
+fn load_multi_vaults<'info>(multi_pool: &Account<'info, MultiPool>, infos: &'info [AccountInfo<'info>]) -> Result<Vec<Account<'info, TokenAccount>>> {
+    let pool_key = multi_pool.key();
+    infos.iter().enumerate().map(|(i, info)| {
+        require_keys_eq!(info.key(), get_associated_token_address(&pool_key, &multi_pool.mints[i]), CustError::InvalidVaultAccount);
+        Account::<TokenAccount>::try_from(info)
+    }).collect()
+}
+
+fn load_multi_depositor_accounts<'info>(multi_pool: &Account<'info, MultiPool>, depositor: &Pubkey, infos: &'info [AccountInfo<'info>]) -> Result<Vec<Account<'info, TokenAccount>>> {
+    infos.iter().enumerate().map(|(i, info)| {
+        let account = Account::<TokenAccount>::try_from(info)?;
+        require_keys_eq!(account.mint, multi_pool.mints[i], CustError::InvalidDepositorAccount);
+        require_keys_eq!(account.owner, *depositor, CustError::InvalidDepositorAccount);
+        Ok(account)
+    }).collect()
+}
+
+#[derive(Accounts)]
+pub struct CreateAmm<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = AMM_SPACE,
+        seeds = [payer.key().as_ref()],
+        bump,
+    )]
+    pub amm: Box<Account<'info, Amm>>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[account]
+#[derive(Default)]
+pub struct Amm {
+    admin: Pubkey,
+    pub bump: u8,
+    // cap on the share of the swap fee, in bps of the fee, a referrer can receive
+    max_referral_bps: u16,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAmm<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [amm.admin.as_ref()],
+        bump = amm.bump,
+        has_one = admin,
+    )]
+    pub amm: Box<Account<'info, Amm>>,
+}
+
+#[derive(Accounts)]
+pub struct CreatePool<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+
+    pub mint_a: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub mint_b: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        seeds = [amm.admin.as_ref()],
+        bump = amm.bump,
+        has_one = admin,
+    )]
+    pub amm: Box<Account<'info, Amm>>,
+    // authority pool
+    #[account(
+        init,
+        payer = admin,
+        space = POOL_SPACE,
+        seeds = [
+            amm.admin.as_ref(),
+            mint_a.key().as_ref(),
+            mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump,        
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = POOL_STATS_SPACE,
+        seeds = [
+            pool_account.key().as_ref(),
+            STATS_SEED
+        ],
+        bump,
+    )]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    // create LP mint account, 权限 
+    #[account(
+        init,
+        payer = admin,
+        seeds = [
+            amm.admin.as_ref(),
+            mint_a.key().as_ref(),
+            mint_b.key().as_ref(), 
+            MINT_SEED],
+        bump,
+        mint::decimals = 6,
+        mint::authority = pool_account,
+    )]
+    pub mint_liquidity: Box<Account<'info, Mint>>,
+    pub token_program: Program<'info, Token>,
+
+}
+
+#[derive(Accounts)]
+pub struct MigratePool<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    pub mint_a: Box<Account<'info, Mint>>,
+    pub mint_b: Box<Account<'info, Mint>>,
+
+    /// CHECK: may predate the current Amm layout, checked and resized in migrate_pool
+    #[account(
+        mut,
+        seeds = [admin.key().as_ref()],
+        bump,
+        owner = crate::ID,
+    )]
+    pub amm: UncheckedAccount<'info>,
+
+    /// CHECK: may predate the current Pool layout, checked and resized in migrate_pool
+    #[account(
+        mut,
+        seeds = [
+            admin.key().as_ref(),
+            mint_a.key().as_ref(),
+            mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump,
+        owner = crate::ID,
+    )]
+    pub pool_account: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = POOL_STATS_SPACE,
+        seeds = [
+            pool_account.key().as_ref(),
+            STATS_SEED
+        ],
+        bump,
+    )]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    #[account(
+        seeds = [
+            admin.key().as_ref(),
+            mint_a.key().as_ref(),
+            mint_b.key().as_ref(),
+            MINT_SEED],
+        bump,
+    )]
+    pub mint_liquidity: Box<Account<'info, Mint>>,
+
+    #[account(
+        associated_token::mint = mint_a,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        associated_token::mint = mint_b,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    // pools created before the locked MIN_LIQUIDITY have no such account yet
+    #[account(
+        init_if_needed,
+        payer = admin,
+        associated_token::mint = mint_liquidity,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_liquidity: Box<Account<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct CreatePoolToken<'info>{
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+
+    pub mint_a: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub mint_b: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub token_program: Program<'info, Token>,
+    // token programs of mint_a and mint_b, the LP mint always uses token_program
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    #[account(
+        seeds = [amm.admin.as_ref()],
+        bump=amm.bump
+    )]
+    pub amm: Box<Account<'info, Amm>>,
+
+    #[account(
+        seeds = [
+            amm.admin.as_ref(),
+            mint_a.key().as_ref(),
+            mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump=pool_account.bump,        
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = mint_a,
+        associated_token::authority = pool_account,
+        associated_token::token_program = token_program_a,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = mint_b,
+        associated_token::authority = pool_account,
+        associated_token::token_program = token_program_b,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        seeds = [
+            amm.admin.as_ref(),
+            mint_a.key().as_ref(),
+            mint_b.key().as_ref(),
+            MINT_SEED],
+        bump,
+    )]
+    pub mint_liquidity: Box<Account<'info, Mint>>,
+
+    // holds the permanently locked MIN_LIQUIDITY, no instruction transfers out of it
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = mint_liquidity,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_liquidity: Box<Account<'info, TokenAccount>>,
+}
+
+#[account]
+#[derive(Default)]
+pub struct Pool{
+    mint_a: Pubkey,
+    mint_b: Pubkey,
+    amm: Pubkey,
+    pub bump: u8,
+    // dynamic fee, fee = base + volatility_accumulator * variable_fee_control / 10000
+    dynamic_fee: bool,
+    base_fee_bps: u16,
+    max_fee_bps: u16,
+    variable_fee_control: u32,
+    decay_period: i64,
+    volatility_accumulator: u64,
+    last_trade_ts: i64,
+    // reserves only change through program instructions, see sync and skim
+    reserve_a: u64,
+    reserve_b: u64,
+    // flash loan, the outstanding loan is cleared by flash_repay in the same transaction
+    flash_fee_bps: u16,
+    flash_loan_active: bool,
+    flash_loan_a: bool,
+    flash_loan_amount: u64,
+    // swaps are only accepted as batch intents, see settle_batch
+    batch_auction: bool,
+    // circuit breaker, post-trade spot price vs a time-weighted price, see check_circuit_breaker
+    twap_price: u64,
+    twap_last_ts: i64,
+    twap_window: i64,
+    breaker_bps: u16,
+    breaker_pause: bool,
+    paused: bool,
+    // external price feed in the Pyth price account layout, default means no oracle
+    oracle: Pubkey,
+    oracle_max_staleness: i64,
+    oracle_max_conf_bps: u16,
+    oracle_max_deviation_bps: u16,
+    // rolling outflow limit as a share of the reserves, see check_outflow_limit
+    outflow_limit_bps: u16,
+    outflow_window: i64,
+    outflow_a: u64,
+    outflow_b: u64,
+    outflow_last_ts: i64,
+    // seconds between request_withdraw and remove_liquidity, 0 means instant withdrawals
+    withdraw_cooldown: i64,
+    // only allowlisted wallets may trade or provide liquidity, see AllowlistEntry
+    permissioned: bool,
+    // layout the account was last written with, see migrate_pool
+    version: u8,
+    // inbound transfers of flagged mints are credited with the vault balance delta, see get_received_amount
+    fee_on_transfer_a: bool,
+    fee_on_transfer_b: bool,
+}
+
+#[account]
+#[derive(Default)]
+pub struct PoolStats{
+    pool: Pubkey,
+    // lifetime totals, volume counts both sides of every trade
+    volume_a: u128,
+    volume_b: u128,
+    fees_a: u128,
+    fees_b: u128,
+    swap_count: u64,
+    deposit_count: u64,
+    withdraw_count: u64,
+    last_trade_ts: i64,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePool<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [amm.admin.as_ref()],
+        bump = amm.bump,
+        has_one = admin,
+    )]
+    pub amm: Box<Account<'info, Amm>>,
+
+    #[account(
+        mut,
+        seeds = [
+            amm.admin.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+}
+
+#[derive(Accounts)]
+#[instruction(user: Pubkey)]
+pub struct AddToAllowlist<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [amm.admin.as_ref()],
+        bump = amm.bump,
+        has_one = admin,
+    )]
+    pub amm: Box<Account<'info, Amm>>,
+
+    #[account(
+        seeds = [
+            amm.admin.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8+32+32+1,
+        seeds = [
+            pool_account.key().as_ref(),
+            user.as_ref(),
+            ALLOWLIST_SEED
+        ],
+        bump,
+    )]
+    pub allowlist: Box<Account<'info, AllowlistEntry>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveFromAllowlist<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [amm.admin.as_ref()],
+        bump = amm.bump,
+        has_one = admin,
+    )]
+    pub amm: Box<Account<'info, Amm>>,
+
+    #[account(
+        seeds = [
+            amm.admin.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        close = admin,
+        constraint = allowlist.pool == pool_account.key() @ CustError::NotAllowlisted,
+    )]
+    pub allowlist: Box<Account<'info, AllowlistEntry>>,
+}
+
+#[account]
+#[derive(Default)]
+pub struct AllowlistEntry{
+    pool: Pubkey,
+    user: Pubkey,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct FlashBorrow<'info> {
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+        has_one = mint_a,
+        has_one = mint_b,
+        constraint = !pool_account.flash_loan_active @ CustError::FlashLoanActive,
+        constraint = !pool_account.permissioned || allowlist.is_some() @ CustError::NotAllowlisted,
+        constraint = !(pool_account.fee_on_transfer_a || pool_account.fee_on_transfer_b) @ CustError::FeeOnTransferUnsupported,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    pub borrower: Signer<'info>,
+    pub mint_a: Box<Account<'info, Mint>>,
+    pub mint_b: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(mut)]
+    pub receiver_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: address is checked to be the instructions sysvar
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    // required when the pool is permissioned
+    #[account(
+        seeds = [
+            pool_account.key().as_ref(),
+            borrower.key().as_ref(),
+            ALLOWLIST_SEED
+        ],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Option<Box<Account<'info, AllowlistEntry>>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// pool_account must stay the first account, flash_borrow looks for it by index
+#[derive(Accounts)]
+pub struct FlashRepay<'info> {
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+        has_one = mint_a,
+        has_one = mint_b,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    pub repayer: Signer<'info>,
+    pub mint_a: Box<Account<'info, Mint>>,
+    pub mint_b: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        token::authority = repayer,
+    )]
+    pub repayer_account: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SyncPool<'info> {
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+        constraint = !pool_account.flash_loan_active @ CustError::FlashLoanActive,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        associated_token::mint = pool_account.mint_a,
+        associated_token::authority = pool_account,
+        associated_token::token_program = token_program_a,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        associated_token::mint = pool_account.mint_b,
+        associated_token::authority = pool_account,
+        associated_token::token_program = token_program_b,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    // token programs of mint_a and mint_b, the LP mint always uses token_program
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SkimPool<'info> {
+    #[account(
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+        has_one = mint_a,
+        has_one = mint_b,
+        constraint = !pool_account.flash_loan_active @ CustError::FlashLoanActive,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    pub mint_a: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub mint_b: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = pool_account,
+        associated_token::token_program = token_program_a,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = pool_account,
+        associated_token::token_program = token_program_b,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = mint_a,
+        token::token_program = token_program_a,
+    )]
+    pub recipient_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = mint_b,
+        token::token_program = token_program_b,
+    )]
+    pub recipient_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    // token programs of mint_a and mint_b
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump,
+        has_one = mint_a,
+        has_one = mint_b,
+        constraint = !pool_account.flash_loan_active @ CustError::FlashLoanActive,
+        constraint = !pool_account.permissioned || allowlist.is_some() @ CustError::NotAllowlisted,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.key().as_ref(),
+            STATS_SEED
+        ],
+        bump = pool_stats.bump,
+    )]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    pub mint_a: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub mint_b: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            mint_a.key().as_ref(),
+            mint_b.key().as_ref(),
+            MINT_SEED,
+        ],
+        bump,
+    )]
+    pub mint_liquidity: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = pool_account,
+        associated_token::token_program = token_program_a,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = pool_account,
+        associated_token::token_program = token_program_b,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_liquidity,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_liquidity: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        associated_token::mint = mint_liquidity,
+        associated_token::authority = depositor,
+    )]
+    pub depositor_account_liquidity: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = depositor,
+        associated_token::token_program = token_program_a,
+    )]
+    pub depositor_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = depositor,
+        associated_token::token_program = token_program_b,
+    )]
+    pub depositor_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+
+
+    // required when the pool is permissioned
+    #[account(
+        seeds = [
+            pool_account.key().as_ref(),
+            depositor.key().as_ref(),
+            ALLOWLIST_SEED
+        ],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Option<Box<Account<'info, AllowlistEntry>>>,
+
+    pub token_program: Program<'info, Token>,
+    // token programs of mint_a and mint_b, the LP mint always uses token_program
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(input_mint: Pubkey)]
+pub struct AddLiquiditySingle<'info> {
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump,
+        has_one = mint_a,
+        has_one = mint_b,
+        constraint = !pool_account.flash_loan_active @ CustError::FlashLoanActive,
+        constraint = !pool_account.batch_auction @ CustError::BatchAuctionActive,
+        constraint = !pool_account.paused @ CustError::PoolPaused,
+        constraint = !pool_account.permissioned @ CustError::PermissionedPool,
+        constraint = !(pool_account.fee_on_transfer_a || pool_account.fee_on_transfer_b) @ CustError::FeeOnTransferUnsupported,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.key().as_ref(),
+            STATS_SEED
+        ],
+        bump = pool_stats.bump,
+    )]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    pub mint_a: Box<Account<'info, Mint>>,
+    pub mint_b: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            mint_a.key().as_ref(),
+            mint_b.key().as_ref(),
+            MINT_SEED,
+        ],
+        bump,
+    )]
+    pub mint_liquidity: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        associated_token::mint = mint_liquidity,
+        associated_token::authority = depositor,
+    )]
+    pub depositor_account_liquidity: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = input_mint,
+        associated_token::authority = depositor,
+    )]
+    pub depositor_account_in: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: parsed as a Pyth price account, required when the pool has an oracle
+    #[account(
+        constraint = oracle.key() == pool_account.oracle @ CustError::InvalidOracle,
+    )]
+    pub oracle: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump,
+        has_one = mint_a,
+        has_one = mint_b,
+        constraint = !pool_account.flash_loan_active @ CustError::FlashLoanActive,
+        constraint = !pool_account.permissioned || allowlist.is_some() @ CustError::NotAllowlisted,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.key().as_ref(),
+            STATS_SEED
+        ],
+        bump = pool_stats.bump,
+    )]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    pub depositor: Signer<'info>,
+    pub mint_a: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub mint_b: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            mint_a.key().as_ref(),
+            mint_b.key().as_ref(),
+            MINT_SEED,
+        ],
+        bump,
+    )]
+    pub mint_liquidity: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = pool_account,
+        associated_token::token_program = token_program_a,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = pool_account,
+        associated_token::token_program = token_program_b,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    // any token account of the mint, the token program checks the burn authority
+    #[account(
+        mut,
+        token::mint = mint_liquidity,
+    )]
+    pub depositor_account_liquidity: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = mint_a,
+        token::token_program = token_program_a,
+    )]
+    pub depositor_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = mint_b,
+        token::token_program = token_program_b,
+    )]
+    pub depositor_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    // required when the pool has a withdraw cooldown
+    #[account(
+        mut,
+        constraint = withdraw_request.owner == depositor.key() @ CustError::WithdrawNotRequested,
+        constraint = withdraw_request.pool == pool_account.key() @ CustError::WithdrawNotRequested,
+    )]
+    pub withdraw_request: Option<Box<Account<'info, WithdrawRequest>>>,
+
+    #[account(mut)]
+    pub withdraw_escrow: Option<Box<Account<'info, TokenAccount>>>,
+
+    // required when the pool is permissioned
+    #[account(
+        seeds = [
+            pool_account.key().as_ref(),
+            depositor.key().as_ref(),
+            ALLOWLIST_SEED
+        ],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Option<Box<Account<'info, AllowlistEntry>>>,
+
+    pub token_program: Program<'info, Token>,
+    // token programs of mint_a and mint_b, the LP mint always uses token_program
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+
+#[derive(Accounts)]
+#[instruction(lp_amount: u64, output_mint: Pubkey)]
+pub struct RemoveLiquiditySingle<'info> {
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump,
+        has_one = mint_a,
+        has_one = mint_b,
+        constraint = !pool_account.flash_loan_active @ CustError::FlashLoanActive,
+        constraint = !pool_account.batch_auction @ CustError::BatchAuctionActive,
+        constraint = !pool_account.paused @ CustError::PoolPaused,
+        constraint = pool_account.withdraw_cooldown == 0 @ CustError::WithdrawCooldownActive,
+        constraint = !pool_account.permissioned @ CustError::PermissionedPool,
+        constraint = !(pool_account.fee_on_transfer_a || pool_account.fee_on_transfer_b) @ CustError::FeeOnTransferUnsupported,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.key().as_ref(),
+            STATS_SEED
+        ],
+        bump = pool_stats.bump,
+    )]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    pub depositor: Signer<'info>,
+    pub mint_a: Box<Account<'info, Mint>>,
+    pub mint_b: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            mint_a.key().as_ref(),
+            mint_b.key().as_ref(),
+            MINT_SEED,
+        ],
+        bump,
+    )]
+    pub mint_liquidity: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    // any token account of the mint, the token program checks the burn authority
+    #[account(
+        mut,
+        token::mint = mint_liquidity,
+    )]
+    pub depositor_account_liquidity: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = output_mint,
+    )]
+    pub depositor_account_out: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: parsed as a Pyth price account, required when the pool has an oracle
+    #[account(
+        constraint = oracle.key() == pool_account.oracle @ CustError::InvalidOracle,
+    )]
+    pub oracle: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SwapLeg {
+    pub amount: u64,
+    pub min_output: u64,
+    pub output_b: bool,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump,
+        has_one = mint_a,
+        has_one = mint_b,
+        constraint = !pool_account.flash_loan_active @ CustError::FlashLoanActive,
+        constraint = !pool_account.batch_auction @ CustError::BatchAuctionActive,
+        constraint = !pool_account.paused @ CustError::PoolPaused,
+        constraint = !pool_account.permissioned || allowlist.is_some() @ CustError::NotAllowlisted,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.key().as_ref(),
+            STATS_SEED
+        ],
+        bump = pool_stats.bump,
+    )]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    #[account(
+        seeds = [pool_account.amm.as_ref()],
+        bump = amm.bump,
+    )]
+    pub amm: Box<Account<'info, Amm>>,
+
+    pub depositor: Signer<'info>,
+    pub mint_a: Box<InterfaceAccount<'info, token_interface::Mint>>,
+    pub mint_b: Box<InterfaceAccount<'info, token_interface::Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = pool_account,
+        associated_token::token_program = token_program_a,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_b,
+        associated_token::authority = pool_account,
+        associated_token::token_program = token_program_b,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    // any token account of the mint, the token program checks the source authority
+    #[account(
+        mut,
+        token::mint = mint_a,
+        token::token_program = token_program_a,
+    )]
+    pub depositor_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = mint_b,
+        token::token_program = token_program_b,
+    )]
+    pub depositor_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    /// CHECK: parsed as a Pyth price account, required when the pool has an oracle
+    #[account(
+        constraint = oracle.key() == pool_account.oracle @ CustError::InvalidOracle,
+    )]
+    pub oracle: Option<UncheckedAccount<'info>>,
+
+    // required when the pool is permissioned
+    #[account(
+        seeds = [
+            pool_account.key().as_ref(),
+            depositor.key().as_ref(),
+            ALLOWLIST_SEED
+        ],
+        bump = allowlist.bump,
+    )]
+    pub allowlist: Option<Box<Account<'info, AllowlistEntry>>>,
+
+    // token programs of mint_a and mint_b
+    pub token_program_a: Interface<'info, TokenInterface>,
+    pub token_program_b: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+}
+
+
+#[derive(Accounts)]
+#[instruction(mints_hash: [u8; 32])]
+pub struct CreateMultiPool<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+
+    #[account(
+        seeds = [amm.admin.as_ref()],
+        bump = amm.bump,
+        has_one = admin,
+    )]
+    pub amm: Box<Account<'info, Amm>>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8+32+32+1+32*MAX_MULTI_TOKENS+8+2+1,
+        seeds = [
+            amm.admin.as_ref(),
+            mints_hash.as_ref(),
+            MULTI_POOL_SEED
+        ],
+        bump,
+    )]
+    pub multi_pool: Box<Account<'info, MultiPool>>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [
+            multi_pool.key().as_ref(),
+            MINT_SEED],
+        bump,
+        mint::decimals = 6,
+        mint::authority = multi_pool,
+    )]
+    pub mint_liquidity: Box<Account<'info, Mint>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CreateMultiPoolToken<'info>{
+    #[account(mut)]
     pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    #[account(
+        seeds = [
+            multi_pool.amm.as_ref(),
+            multi_pool.mints_hash.as_ref(),
+            MULTI_POOL_SEED
+        ],
+        bump = multi_pool.bump,
+    )]
+    pub multi_pool: Box<Account<'info, MultiPool>>,
+
+    #[account(
+        seeds = [
+            multi_pool.key().as_ref(),
+            MINT_SEED,
+        ],
+        bump,
+    )]
+    pub mint_liquidity: Box<Account<'info, Mint>>,
+
+    // holds the permanently locked MIN_LIQUIDITY, no instruction transfers out of it
+    #[account(
+        init_if_needed,
+        payer = payer,
+        associated_token::mint = mint_liquidity,
+        associated_token::authority = multi_pool,
+    )]
+    pub pool_account_liquidity: Box<Account<'info, TokenAccount>>,
+}
+
+#[account]
+#[derive(Default)]
+pub struct MultiPool{
+    amm: Pubkey,
+    mints_hash: [u8; 32],
+    num_tokens: u8,
+    mints: [Pubkey; MAX_MULTI_TOKENS],
+    amp: u64,
+    fee_bps: u16,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct AddLiquidityMulti<'info> {
+    #[account(
+        seeds = [
+            multi_pool.amm.as_ref(),
+            multi_pool.mints_hash.as_ref(),
+            MULTI_POOL_SEED
+        ],
+        bump = multi_pool.bump,
+    )]
+    pub multi_pool: Box<Account<'info, MultiPool>>,
+
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            multi_pool.key().as_ref(),
+            MINT_SEED,
+        ],
+        bump,
+    )]
+    pub mint_liquidity: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_liquidity,
+        associated_token::authority = multi_pool,
+    )]
+    pub pool_account_liquidity: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        associated_token::mint = mint_liquidity,
+        associated_token::authority = depositor,
+    )]
+    pub depositor_account_liquidity: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveLiquidityMulti<'info> {
+    #[account(
+        seeds = [
+            multi_pool.amm.as_ref(),
+            multi_pool.mints_hash.as_ref(),
+            MULTI_POOL_SEED
+        ],
+        bump = multi_pool.bump,
+    )]
+    pub multi_pool: Box<Account<'info, MultiPool>>,
+
+    pub depositor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            multi_pool.key().as_ref(),
+            MINT_SEED,
+        ],
+        bump,
+    )]
+    pub mint_liquidity: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint_liquidity,
+        associated_token::authority = depositor,
+    )]
+    pub depositor_account_liquidity: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SwapMulti<'info> {
+    #[account(
+        seeds = [
+            multi_pool.amm.as_ref(),
+            multi_pool.mints_hash.as_ref(),
+            MULTI_POOL_SEED
+        ],
+        bump = multi_pool.bump,
+    )]
+    pub multi_pool: Box<Account<'info, MultiPool>>,
+
+    pub depositor: Signer<'info>,
+
+    #[account(
+        mut,
+        token::authority = depositor,
+    )]
+    pub depositor_account_in: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::authority = depositor,
+    )]
+    pub depositor_account_out: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+
+#[derive(Accounts)]
+#[instruction(order_id: u64)]
+pub struct PlaceOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+        constraint = (input_mint.key() == pool_account.mint_a && output_mint.key() == pool_account.mint_b)
+            || (input_mint.key() == pool_account.mint_b && output_mint.key() == pool_account.mint_a) @ CustError::InvalidMint,
+        constraint = !pool_account.permissioned @ CustError::PermissionedPool,
+        constraint = !(pool_account.fee_on_transfer_a || pool_account.fee_on_transfer_b) @ CustError::FeeOnTransferUnsupported,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    pub input_mint: Box<Account<'info, Mint>>,
+    pub output_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8+32+32+32+32+8+8+8+8+1,
+        seeds = [
+            pool_account.key().as_ref(),
+            owner.key().as_ref(),
+            &order_id.to_le_bytes(),
+            ORDER_SEED
+        ],
+        bump,
+    )]
+    pub order: Box<Account<'info, LimitOrder>>,
+
+    #[account(
+        init,
+        payer = owner,
+        associated_token::mint = input_mint,
+        associated_token::authority = order,
+    )]
+    pub escrow: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = input_mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_account_in: Box<Account<'info, TokenAccount>>,
+
+    // created here so a fill never fails on a missing output account
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = output_mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_account_out: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[account]
+#[derive(Default)]
+pub struct LimitOrder{
+    owner: Pubkey,
+    pool: Pubkey,
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    order_id: u64,
+    amount_in: u64,
+    // limit price is min_output / amount_in
+    min_output: u64,
+    tip: u64,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner,
+    )]
+    pub order: Box<Account<'info, LimitOrder>>,
+
+    #[account(
+        mut,
+        associated_token::mint = order.input_mint,
+        associated_token::authority = order,
+    )]
+    pub escrow: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = order.input_mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_account_in: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct FillOrder<'info> {
+    pub filler: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+        has_one = mint_a,
+        has_one = mint_b,
+        constraint = !pool_account.flash_loan_active @ CustError::FlashLoanActive,
+        constraint = !pool_account.batch_auction @ CustError::BatchAuctionActive,
+        constraint = !pool_account.paused @ CustError::PoolPaused,
+        constraint = !pool_account.permissioned @ CustError::PermissionedPool,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.key().as_ref(),
+            STATS_SEED
+        ],
+        bump = pool_stats.bump,
+    )]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    pub mint_a: Box<Account<'info, Mint>>,
+    pub mint_b: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner,
+        constraint = order.pool == pool_account.key() @ CustError::InvalidOrder,
+    )]
+    pub order: Box<Account<'info, LimitOrder>>,
+
+    /// CHECK: order owner, receives the escrow and order rent
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = order.input_mint,
+        associated_token::authority = order,
+    )]
+    pub escrow: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = order.output_mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_account_out: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = order.input_mint,
+    )]
+    pub filler_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool_account.mint_a,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool_account.mint_b,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    /// CHECK: parsed as a Pyth price account, required when the pool has an oracle
+    #[account(
+        constraint = oracle.key() == pool_account.oracle @ CustError::InvalidOracle,
+    )]
+    pub oracle: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(dca_id: u64)]
+pub struct CreateDca<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+        constraint = (input_mint.key() == pool_account.mint_a && output_mint.key() == pool_account.mint_b)
+            || (input_mint.key() == pool_account.mint_b && output_mint.key() == pool_account.mint_a) @ CustError::InvalidMint,
+        constraint = !pool_account.permissioned @ CustError::PermissionedPool,
+        constraint = !(pool_account.fee_on_transfer_a || pool_account.fee_on_transfer_b) @ CustError::FeeOnTransferUnsupported,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    pub input_mint: Box<Account<'info, Mint>>,
+    pub output_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8+32+32+32+32+8+8+8+8+8+8+1,
+        seeds = [
+            pool_account.key().as_ref(),
+            owner.key().as_ref(),
+            &dca_id.to_le_bytes(),
+            DCA_SEED
+        ],
+        bump,
+    )]
+    pub dca: Box<Account<'info, DcaOrder>>,
+
+    #[account(
+        init,
+        payer = owner,
+        associated_token::mint = input_mint,
+        associated_token::authority = dca,
+    )]
+    pub escrow: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = input_mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_account_in: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = output_mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_account_out: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[account]
+#[derive(Default)]
+pub struct DcaOrder{
+    owner: Pubkey,
+    pool: Pubkey,
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    dca_id: u64,
+    slice_amount: u64,
+    interval: i64,
+    // output per input scaled by PRICE_SCALE, checked on every slice
+    min_price: u64,
+    keeper_reward: u64,
+    last_executed_ts: i64,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct CancelDca<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner,
+    )]
+    pub dca: Box<Account<'info, DcaOrder>>,
+
+    #[account(
+        mut,
+        associated_token::mint = dca.input_mint,
+        associated_token::authority = dca,
+    )]
+    pub escrow: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = dca.input_mint,
+        associated_token::authority = owner,
+    )]
+    pub owner_account_in: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteDcaSlice<'info> {
+    pub keeper: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+        has_one = mint_a,
+        has_one = mint_b,
+        constraint = !pool_account.flash_loan_active @ CustError::FlashLoanActive,
+        constraint = !pool_account.batch_auction @ CustError::BatchAuctionActive,
+        constraint = !pool_account.paused @ CustError::PoolPaused,
+        constraint = !pool_account.permissioned @ CustError::PermissionedPool,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.key().as_ref(),
+            STATS_SEED
+        ],
+        bump = pool_stats.bump,
+    )]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    pub mint_a: Box<Account<'info, Mint>>,
+    pub mint_b: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = dca.pool == pool_account.key() @ CustError::InvalidOrder,
+    )]
+    pub dca: Box<Account<'info, DcaOrder>>,
+
+    #[account(
+        mut,
+        associated_token::mint = dca.input_mint,
+        associated_token::authority = dca,
+    )]
+    pub escrow: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = dca.output_mint,
+        associated_token::authority = dca.owner,
+    )]
+    pub owner_account_out: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = dca.input_mint,
+    )]
+    pub keeper_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool_account.mint_a,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool_account.mint_b,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    /// CHECK: parsed as a Pyth price account, required when the pool has an oracle
+    #[account(
+        constraint = oracle.key() == pool_account.oracle @ CustError::InvalidOracle,
+    )]
+    pub oracle: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RequestWithdraw<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            MINT_SEED,
+        ],
+        bump,
+    )]
+    pub mint_liquidity: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = 8+32+32+8+1,
+        seeds = [
+            pool_account.key().as_ref(),
+            owner.key().as_ref(),
+            WITHDRAW_SEED
+        ],
+        bump,
+    )]
+    pub withdraw_request: Box<Account<'info, WithdrawRequest>>,
+
+    #[account(
+        init,
+        payer = owner,
+        associated_token::mint = mint_liquidity,
+        associated_token::authority = withdraw_request,
+    )]
+    pub withdraw_escrow: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = mint_liquidity,
+    )]
+    pub owner_account_liquidity: Box<Account<'info, TokenAccount>>,
 
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct CancelWithdraw<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner,
+    )]
+    pub withdraw_request: Box<Account<'info, WithdrawRequest>>,
+
+    #[account(
+        mut,
+        token::authority = withdraw_request,
+        constraint = withdraw_escrow.key() == get_associated_token_address(&withdraw_request.key(), &withdraw_escrow.mint) @ CustError::WithdrawNotRequested,
+    )]
+    pub withdraw_escrow: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = withdraw_escrow.mint,
+    )]
+    pub owner_account_liquidity: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
 #[account]
 #[derive(Default)]
-pub struct Amm {
-    admin: Pubkey,
+pub struct WithdrawRequest{
+    owner: Pubkey,
+    pool: Pubkey,
+    requested_ts: i64,
     pub bump: u8,
-
 }
 
 #[derive(Accounts)]
-pub struct CreatePool<'info> {
+#[instruction(slot: u64, amount_in: u64, sell_a: bool)]
+pub struct QueueSwap<'info> {
     #[account(mut)]
-    pub admin: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+        has_one = mint_a,
+        has_one = mint_b,
+        constraint = pool_account.batch_auction @ CustError::BatchAuctionDisabled,
+        constraint = !pool_account.permissioned @ CustError::PermissionedPool,
+        constraint = !(pool_account.fee_on_transfer_a || pool_account.fee_on_transfer_b) @ CustError::FeeOnTransferUnsupported,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
 
     pub mint_a: Box<Account<'info, Mint>>,
     pub mint_b: Box<Account<'info, Mint>>,
 
     #[account(
-        seeds = [amm.admin.as_ref()],
-        bump = amm.bump,
-        has_one = admin,
+        init_if_needed,
+        payer = owner,
+        space = 8+32+8+8+8+1+8+8+1,
+        seeds = [
+            pool_account.key().as_ref(),
+            &slot.to_le_bytes(),
+            BATCH_SEED
+        ],
+        bump,
     )]
-    pub amm: Box<Account<'info, Amm>>,
-    // authority pool
+    pub batch: Box<Account<'info, BatchAuction>>,
+
     #[account(
         init,
-        payer = admin,
-        space = 8+32+32+32+1,
+        payer = owner,
+        space = 8+32+32+8+1+1,
         seeds = [
-            amm.admin.as_ref(),
-            mint_a.key().as_ref(),
-            mint_b.key().as_ref(),
+            batch.key().as_ref(),
+            owner.key().as_ref(),
+            INTENT_SEED
+        ],
+        bump,
+    )]
+    pub intent: Box<Account<'info, BatchIntent>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint_a,
+        associated_token::authority = batch,
+    )]
+    pub batch_account_a: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint_b,
+        associated_token::authority = batch,
+    )]
+    pub batch_account_b: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = if sell_a { mint_a.key() } else { mint_b.key() },
+        token::authority = owner,
+    )]
+    pub owner_account_in: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleBatch<'info> {
+    pub settler: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
             POOL_SEED
         ],
-        bump,        
+        bump = pool_account.bump,
+        has_one = mint_a,
+        has_one = mint_b,
+        constraint = !pool_account.flash_loan_active @ CustError::FlashLoanActive,
+        constraint = !pool_account.paused @ CustError::PoolPaused,
+        constraint = !pool_account.permissioned @ CustError::PermissionedPool,
     )]
     pub pool_account: Box<Account<'info, Pool>>,
 
-    // create LP mint account, 权限 
     #[account(
-        init,
-        payer = admin,
+        mut,
         seeds = [
-            amm.admin.as_ref(),
-            mint_a.key().as_ref(),
-            mint_b.key().as_ref(), 
-            MINT_SEED],
-        bump,
-        mint::decimals = 6,
-        mint::authority = pool_account,
+            pool_account.key().as_ref(),
+            STATS_SEED
+        ],
+        bump = pool_stats.bump,
     )]
-    pub mint_liquidity: Box<Account<'info, Mint>>,
-    pub token_program: Program<'info, Token>,
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    pub mint_a: Box<Account<'info, Mint>>,
+    pub mint_b: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = batch.pool == pool_account.key() @ CustError::InvalidOrder,
+        constraint = !batch.settled @ CustError::BatchSettled,
+    )]
+    pub batch: Box<Account<'info, BatchAuction>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool_account.mint_a,
+        associated_token::authority = batch,
+    )]
+    pub batch_account_a: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool_account.mint_b,
+        associated_token::authority = batch,
+    )]
+    pub batch_account_b: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool_account.mint_a,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool_account.mint_b,
+        associated_token::authority = pool_account,
+    )]
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
+
+    /// CHECK: parsed as a Pyth price account, required when the pool has an oracle
+    #[account(
+        constraint = oracle.key() == pool_account.oracle @ CustError::InvalidOracle,
+    )]
+    pub oracle: Option<UncheckedAccount<'info>>,
 
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct CreatePoolToken<'info>{
+pub struct ClaimBatch<'info> {
     #[account(mut)]
-    pub payer: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub owner: Signer<'info>,
+
+    #[account(
+        constraint = batch.pool == pool_account.key() @ CustError::InvalidOrder,
+        constraint = batch.settled @ CustError::BatchNotSettled,
+    )]
+    pub batch: Box<Account<'info, BatchAuction>>,
+
+    #[account(
+        seeds = [
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
+            POOL_SEED
+        ],
+        bump = pool_account.bump,
+    )]
+    pub pool_account: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner,
+        has_one = batch,
+    )]
+    pub intent: Box<Account<'info, BatchIntent>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool_account.mint_a,
+        associated_token::authority = batch,
+    )]
+    pub batch_account_a: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool_account.mint_b,
+        associated_token::authority = batch,
+    )]
+    pub batch_account_b: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = if intent.sell_a { pool_account.mint_b } else { pool_account.mint_a },
+        token::authority = owner,
+    )]
+    pub owner_account_out: Box<Account<'info, TokenAccount>>,
 
-    pub mint_a: Box<Account<'info, Mint>>,
-    pub mint_b: Box<Account<'info, Mint>>,
     pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct RefundIntent<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
 
     #[account(
-        seeds = [amm.admin.as_ref()],
-        bump=amm.bump
+        mut,
+        constraint = batch.pool == pool_account.key() @ CustError::InvalidOrder,
+        constraint = !batch.settled @ CustError::BatchSettled,
     )]
-    pub amm: Box<Account<'info, Amm>>,
+    pub batch: Box<Account<'info, BatchAuction>>,
 
     #[account(
         seeds = [
-            amm.admin.as_ref(),
-            mint_a.key().as_ref(),
-            mint_b.key().as_ref(),
+            pool_account.amm.as_ref(),
+            pool_account.mint_a.key().as_ref(),
+            pool_account.mint_b.key().as_ref(),
             POOL_SEED
         ],
-        bump=pool_account.bump,        
+        bump = pool_account.bump,
     )]
     pub pool_account: Box<Account<'info, Pool>>,
 
     #[account(
-        init,
-        payer = payer,
-        associated_token::mint = mint_a,
-        associated_token::authority = pool_account,
+        mut,
+        close = owner,
+        has_one = owner,
+        has_one = batch,
     )]
-    pub pool_account_a: Box<Account<'info, TokenAccount>>,
+    pub intent: Box<Account<'info, BatchIntent>>,
 
     #[account(
-        init,
-        payer = payer,
-        associated_token::mint = mint_b,
-        associated_token::authority = pool_account,
+        mut,
+        associated_token::mint = pool_account.mint_a,
+        associated_token::authority = batch,
+    )]
+    pub batch_account_a: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = pool_account.mint_b,
+        associated_token::authority = batch,
     )]
-    pub pool_account_b: Box<Account<'info, TokenAccount>>,
+    pub batch_account_b: Box<Account<'info, TokenAccount>>,
 
+    #[account(
+        mut,
+        token::mint = if intent.sell_a { pool_account.mint_a } else { pool_account.mint_b },
+        token::authority = owner,
+    )]
+    pub owner_account_in: Box<Account<'info, TokenAccount>>,
 
+    pub token_program: Program<'info, Token>,
 }
 
 #[account]
 #[derive(Default)]
-pub struct Pool{
-    mint_a: Pubkey,
-    mint_b: Pubkey,
-    amm: Pubkey,
+pub struct BatchAuction{
+    pool: Pubkey,
+    slot: u64,
+    total_in_a: u64,
+    total_in_b: u64,
+    settled: bool,
+    // owed to the intents selling b and a respectively, fixed by settle_batch
+    payout_a: u64,
+    payout_b: u64,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(Default)]
+pub struct BatchIntent{
+    owner: Pubkey,
+    batch: Pubkey,
+    amount_in: u64,
+    sell_a: bool,
     pub bump: u8,
 }
 
 #[derive(Accounts)]
-pub struct AddLiquidity<'info> {
+#[instruction(commit_id: u64)]
+pub struct CommitSwap<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
     #[account(
         seeds = [
             pool_account.amm.as_ref(),
@@ -409,66 +4433,43 @@ pub struct AddLiquidity<'info> {
             pool_account.mint_b.key().as_ref(),
             POOL_SEED
         ],
-        bump,
-        has_one = mint_a,
-        has_one = mint_b,
+        bump = pool_account.bump,
+        constraint = input_mint.key() == pool_account.mint_a || input_mint.key() == pool_account.mint_b @ CustError::InvalidMint,
+        constraint = !pool_account.permissioned @ CustError::PermissionedPool,
+        constraint = !(pool_account.fee_on_transfer_a || pool_account.fee_on_transfer_b) @ CustError::FeeOnTransferUnsupported,
     )]
     pub pool_account: Box<Account<'info, Pool>>,
 
-    #[account(mut)]
-    pub depositor: Signer<'info>,
-    pub mint_a: Box<Account<'info, Mint>>,
-    pub mint_b: Box<Account<'info, Mint>>,
+    pub input_mint: Box<Account<'info, Mint>>,
 
     #[account(
-        mut,
+        init,
+        payer = owner,
+        space = 8+32+32+32+8+8+32+8+1,
         seeds = [
-            pool_account.amm.as_ref(),
-            mint_a.key().as_ref(),
-            mint_b.key().as_ref(),
-            MINT_SEED,
+            pool_account.key().as_ref(),
+            owner.key().as_ref(),
+            &commit_id.to_le_bytes(),
+            COMMIT_SEED
         ],
         bump,
     )]
-    pub mint_liquidity: Box<Account<'info, Mint>>,
-
-    #[account(
-        mut,
-        associated_token::mint = mint_a,
-        associated_token::authority = pool_account,
-    )]
-    pub pool_account_a: Box<Account<'info, TokenAccount>>,
-
-    #[account(
-        mut,
-        associated_token::mint = mint_b,
-        associated_token::authority = pool_account,
-    )]
-    pub pool_account_b: Box<Account<'info, TokenAccount>>,
-
-    #[account(
-        init_if_needed,
-        payer = depositor,
-        associated_token::mint = mint_liquidity,
-        associated_token::authority = depositor,
-    )]
-    pub depositor_account_liquidity: Box<Account<'info, TokenAccount>>,
+    pub commit: Box<Account<'info, SwapCommit>>,
 
     #[account(
-        mut,
-        associated_token::mint = mint_a,
-        associated_token::authority = depositor,
+        init,
+        payer = owner,
+        associated_token::mint = input_mint,
+        associated_token::authority = commit,
     )]
-    pub depositor_account_a: Box<Account<'info, TokenAccount>>,
+    pub escrow: Box<Account<'info, TokenAccount>>,
 
     #[account(
         mut,
-        associated_token::mint = mint_b,
-        associated_token::authority = depositor,
+        token::mint = input_mint,
+        token::authority = owner,
     )]
-    pub depositor_account_b: Box<Account<'info, TokenAccount>>,
-
-
+    pub owner_account_in: Box<Account<'info, TokenAccount>>,
 
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
@@ -476,131 +4477,128 @@ pub struct AddLiquidity<'info> {
 }
 
 #[derive(Accounts)]
-pub struct RemoveLiquidity<'info> {
+pub struct RevealSwap<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
     #[account(
+        mut,
         seeds = [
             pool_account.amm.as_ref(),
             pool_account.mint_a.key().as_ref(),
             pool_account.mint_b.key().as_ref(),
             POOL_SEED
         ],
-        bump,
+        bump = pool_account.bump,
         has_one = mint_a,
         has_one = mint_b,
+        constraint = !pool_account.flash_loan_active @ CustError::FlashLoanActive,
+        constraint = !pool_account.batch_auction @ CustError::BatchAuctionActive,
+        constraint = !pool_account.paused @ CustError::PoolPaused,
+        constraint = !pool_account.permissioned @ CustError::PermissionedPool,
     )]
     pub pool_account: Box<Account<'info, Pool>>,
 
-    pub depositor: Signer<'info>,
-    pub mint_a: Box<Account<'info, Mint>>,
-    pub mint_b: Box<Account<'info, Mint>>,
-
     #[account(
         mut,
         seeds = [
-            pool_account.amm.as_ref(),
-            mint_a.key().as_ref(),
-            mint_b.key().as_ref(),
-            MINT_SEED,
+            pool_account.key().as_ref(),
+            STATS_SEED
         ],
-        bump,
+        bump = pool_stats.bump,
     )]
-    pub mint_liquidity: Box<Account<'info, Mint>>,
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    pub mint_a: Box<Account<'info, Mint>>,
+    pub mint_b: Box<Account<'info, Mint>>,
 
     #[account(
         mut,
-        associated_token::mint = mint_a,
-        associated_token::authority = pool_account,
+        close = owner,
+        has_one = owner,
+        constraint = commit.pool == pool_account.key() @ CustError::InvalidOrder,
     )]
-    pub pool_account_a: Box<Account<'info, TokenAccount>>,
+    pub commit: Box<Account<'info, SwapCommit>>,
 
     #[account(
         mut,
-        associated_token::mint = mint_b,
-        associated_token::authority = pool_account,
+        associated_token::mint = commit.input_mint,
+        associated_token::authority = commit,
     )]
-    pub pool_account_b: Box<Account<'info, TokenAccount>>,
+    pub escrow: Box<Account<'info, TokenAccount>>,
 
     #[account(
         mut,
-        associated_token::mint = mint_liquidity,
-        associated_token::authority = depositor,
+        token::mint = if commit.input_mint == pool_account.mint_a { pool_account.mint_b } else { pool_account.mint_a },
+        token::authority = owner,
     )]
-    pub depositor_account_liquidity: Box<Account<'info, TokenAccount>>,
+    pub owner_account_out: Box<Account<'info, TokenAccount>>,
 
     #[account(
         mut,
-        associated_token::mint = mint_a,
-        associated_token::authority = depositor,
+        associated_token::mint = pool_account.mint_a,
+        associated_token::authority = pool_account,
     )]
-    pub depositor_account_a: Box<Account<'info, TokenAccount>>,
+    pub pool_account_a: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
 
     #[account(
         mut,
-        associated_token::mint = mint_b,
-        associated_token::authority = depositor,
+        associated_token::mint = pool_account.mint_b,
+        associated_token::authority = pool_account,
     )]
-    pub depositor_account_b: Box<Account<'info, TokenAccount>>,
+    pub pool_account_b: Box<InterfaceAccount<'info, token_interface::TokenAccount>>,
 
+    /// CHECK: parsed as a Pyth price account, required when the pool has an oracle
+    #[account(
+        constraint = oracle.key() == pool_account.oracle @ CustError::InvalidOracle,
+    )]
+    pub oracle: Option<UncheckedAccount<'info>>,
 
     pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
 }
 
-
 #[derive(Accounts)]
-pub struct Swap<'info> {
-    #[account(
-        seeds = [
-            pool_account.amm.as_ref(),
-            pool_account.mint_a.key().as_ref(),
-            pool_account.mint_b.key().as_ref(),
-            POOL_SEED
-        ],
-        bump,
-        has_one = mint_a,
-        has_one = mint_b,
-    )]
-    pub pool_account: Box<Account<'info, Pool>>,
-
-    pub depositor: Signer<'info>,
-    pub mint_a: Box<Account<'info, Mint>>,
-    pub mint_b: Box<Account<'info, Mint>>,
-
-    #[account(
-        mut,
-        associated_token::mint = mint_a,
-        associated_token::authority = pool_account,
-    )]
-    pub pool_account_a: Box<Account<'info, TokenAccount>>,
+pub struct RefundCommit<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
 
     #[account(
         mut,
-        associated_token::mint = mint_b,
-        associated_token::authority = pool_account,
+        close = owner,
+        has_one = owner,
     )]
-    pub pool_account_b: Box<Account<'info, TokenAccount>>,
+    pub commit: Box<Account<'info, SwapCommit>>,
 
     #[account(
         mut,
-        associated_token::mint = mint_a,
-        associated_token::authority = depositor,
+        associated_token::mint = commit.input_mint,
+        associated_token::authority = commit,
     )]
-    pub depositor_account_a: Box<Account<'info, TokenAccount>>,
+    pub escrow: Box<Account<'info, TokenAccount>>,
 
     #[account(
         mut,
-        associated_token::mint = mint_b,
-        associated_token::authority = depositor,
+        token::mint = commit.input_mint,
+        token::authority = owner,
     )]
-    pub depositor_account_b: Box<Account<'info, TokenAccount>>,
+    pub owner_account_in: Box<Account<'info, TokenAccount>>,
 
     pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-
 }
 
+#[account]
+#[derive(Default)]
+pub struct SwapCommit{
+    owner: Pubkey,
+    pool: Pubkey,
+    input_mint: Pubkey,
+    commit_id: u64,
+    amount_in: u64,
+    // hash(min_output, salt)
+    commitment: [u8; 32],
+    slot: u64,
+    pub bump: u8,
+}
 
 #[event]
 pub struct AMMEvent {
@@ -613,7 +4611,8 @@ pub struct AddLiquidityEvent {
     pub message: String,
     pub operator: Pubkey,
     pub amount_a: u64,
-    pub amount_b: u64
+    pub amount_b: u64,
+    pub lp_token: u64,
 }
 
 #[event]
@@ -621,7 +4620,47 @@ pub struct SwapEvent {
     pub message: String,
     pub operator: Pubkey,
     pub input: u64,
-    pub output: u64
+    pub output: u64,
+    pub referrer: Option<Pubkey>,
+    pub referral_amount: u64,
+}
+
+#[event]
+pub struct SwapBatchEvent {
+    pub message: String,
+    pub operator: Pubkey,
+    pub legs: u32,
+    pub input_a: u64,
+    pub input_b: u64,
+    pub output_a: u64,
+    pub output_b: u64,
+}
+
+#[event]
+pub struct BatchEvent {
+    pub message: String,
+    pub batch: Pubkey,
+    pub total_in_a: u64,
+    pub total_in_b: u64,
+    pub payout_a: u64,
+    pub payout_b: u64,
+}
+
+#[event]
+pub struct CircuitBreakerEvent {
+    pub message: String,
+    pub pool: Pubkey,
+    pub twap_price: u64,
+    pub spot_price: u64,
+    pub deviation_bps: u64,
+    pub paused: bool,
+}
+
+#[event]
+pub struct AllowlistEvent {
+    pub message: String,
+    pub pool: Pubkey,
+    pub user: Pubkey,
 }
 
 #[event]
@@ -631,6 +4670,45 @@ pub struct RemoveLiquidityEvent {
     pub lp_token: u64,
 }
 
+#[event]
+pub struct FlashLoanEvent {
+    pub message: String,
+    pub operator: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct OrderEvent {
+    pub message: String,
+    pub owner: Pubkey,
+    pub order: Pubkey,
+    pub amount_in: u64,
+    pub output: u64,
+}
+
+#[event]
+pub struct SyncEvent {
+    pub message: String,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+}
+
+#[event]
+pub struct SkimEvent {
+    pub message: String,
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+
+#[event]
+pub struct MultiLiquidityEvent {
+    pub message: String,
+    pub operator: Pubkey,
+    pub amounts: Vec<u64>,
+    pub lp_token: u64,
+}
+
 
 
 
@@ -649,7 +4727,175 @@ pub enum CustError {
     InsufficentOutputTokenB,
 
     #[msg("Insufficient output amount")]
-    InsufficientOutputAmount
+    InsufficientOutputAmount,
+
+    #[msg("Invalid number of pool tokens")]
+    InvalidTokenCount,
+
+    #[msg("Duplicate mint in pool")]
+    DuplicateMint,
+
+    #[msg("Pool mints do not match the mints hash")]
+    MintHashMismatch,
+
+    #[msg("Pool mints must have the same decimals")]
+    DecimalsMismatch,
+
+    #[msg("Invalid amplification coefficient")]
+    InvalidAmp,
+
+    #[msg("Invalid fee")]
+    InvalidFee,
+
+    #[msg("Invalid mint")]
+    InvalidMint,
+
+    #[msg("Invalid token index")]
+    InvalidTokenIndex,
+
+    #[msg("Invalid pool vault account")]
+    InvalidVaultAccount,
+
+    #[msg("Invalid depositor token account")]
+    InvalidDepositorAccount,
+
+    #[msg("Insufficient liquidity minted")]
+    InsufficientLiquidityMinted,
+
+    #[msg("Math overflow")]
+    MathOverflow,
+
+    #[msg("Pool has no liquidity")]
+    EmptyPool,
+
+    #[msg("Insufficient liquidity")]
+    InsufficientLiquidity,
+
+    #[msg("A flash loan is outstanding on this pool")]
+    FlashLoanActive,
+
+    #[msg("No flash loan to repay")]
+    FlashLoanNotActive,
+
+    #[msg("Flash borrow without a matching flash repay")]
+    FlashLoanNoRepay,
+
+    #[msg("Flash borrow must be a top level instruction")]
+    FlashLoanCpi,
+
+    #[msg("Invalid receiver program")]
+    InvalidReceiverProgram,
+
+    #[msg("Insufficient input amount")]
+    InsufficientInputAmount,
+
+    #[msg("Pool invariant violated")]
+    InvariantViolated,
+
+    #[msg("Invalid order")]
+    InvalidOrder,
+
+    #[msg("Pool price has not reached the order limit")]
+    OrderPriceNotReached,
+
+    #[msg("DCA interval has not elapsed")]
+    DcaIntervalNotElapsed,
+
+    #[msg("DCA deposit is fully executed")]
+    DcaCompleted,
+
+    #[msg("Price impact too high")]
+    PriceImpactTooHigh,
+
+    #[msg("Invalid referral fee")]
+    InvalidReferralFee,
+
+    #[msg("Invalid swap batch")]
+    InvalidBatch,
+
+    #[msg("Pool only accepts batch auction swaps")]
+    BatchAuctionActive,
+
+    #[msg("Batch auction is disabled")]
+    BatchAuctionDisabled,
+
+    #[msg("Invalid batch slot")]
+    InvalidBatchSlot,
+
+    #[msg("Batch slot is not over")]
+    BatchNotClosed,
+
+    #[msg("Batch already settled")]
+    BatchSettled,
+
+    #[msg("Batch not settled")]
+    BatchNotSettled,
+
+    #[msg("Commit can not be revealed yet")]
+    CommitTooEarly,
+
+    #[msg("Commit expired")]
+    CommitExpired,
+
+    #[msg("Commit not expired")]
+    CommitNotExpired,
+
+    #[msg("Revealed parameters do not match the commitment")]
+    InvalidCommitment,
+
+    #[msg("Invalid circuit breaker config")]
+    InvalidCircuitBreaker,
+
+    #[msg("Price deviates too far from the TWAP")]
+    CircuitBreakerTriggered,
+
+    #[msg("Pool is paused")]
+    PoolPaused,
+
+    #[msg("Invalid oracle account")]
+    InvalidOracle,
+
+    #[msg("Oracle price is stale")]
+    OracleStale,
+
+    #[msg("Oracle confidence interval too wide")]
+    OracleConfidenceTooWide,
+
+    #[msg("Pool price deviates too far from the oracle")]
+    OraclePriceDeviation,
+
+    #[msg("Invalid outflow limit")]
+    InvalidOutflowLimit,
+
+    #[msg("Outflow limit exceeded for the current window")]
+    OutflowLimitExceeded,
+
+    #[msg("Invalid withdraw cooldown")]
+    InvalidWithdrawCooldown,
+
+    #[msg("Pool has no withdraw cooldown")]
+    WithdrawCooldownDisabled,
+
+    #[msg("Withdraw cooldown has not elapsed")]
+    WithdrawCooldownActive,
+
+    #[msg("No matching withdraw request")]
+    WithdrawNotRequested,
+
+    #[msg("Depositor is not on the pool allowlist")]
+    NotAllowlisted,
+
+    #[msg("Not available on permissioned pools")]
+    PermissionedPool,
+
+    #[msg("Batch can not be refunded yet")]
+    BatchNotExpired,
+
+    #[msg("Not available on pools with fee-on-transfer mints")]
+    FeeOnTransferUnsupported,
+
+    #[msg("Mints with a transfer fee must be flagged as fee-on-transfer")]
+    FeeOnTransferNotFlagged
 }
 
 
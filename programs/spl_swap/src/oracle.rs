@@ -0,0 +1,47 @@
+use anchor_lang::prelude::*;
+
+use crate::CustError;
+
+// Pyth v2 price account, only the fields the swap checks are read
+const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+const PYTH_VERSION: u32 = 2;
+const PYTH_PRICE_ACCOUNT: u32 = 3;
+const PYTH_STATUS_TRADING: u32 = 1;
+
+const EXPO_OFFSET: usize = 20;
+const TIMESTAMP_OFFSET: usize = 96;
+const AGG_PRICE_OFFSET: usize = 208;
+const AGG_CONF_OFFSET: usize = 216;
+const AGG_STATUS_OFFSET: usize = 224;
+const PYTH_PRICE_MIN_LEN: usize = 240;
+
+pub struct OraclePrice {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+pub fn load_pyth_price(data: &[u8]) -> Result<OraclePrice> {
+    require!(data.len() >= PYTH_PRICE_MIN_LEN, CustError::InvalidOracle);
+    require!(read_u32(data, 0) == PYTH_MAGIC, CustError::InvalidOracle);
+    require!(read_u32(data, 4) == PYTH_VERSION, CustError::InvalidOracle);
+    require!(read_u32(data, 8) == PYTH_PRICE_ACCOUNT, CustError::InvalidOracle);
+    require!(read_u32(data, AGG_STATUS_OFFSET) == PYTH_STATUS_TRADING, CustError::InvalidOracle);
+    let price = read_u64(data, AGG_PRICE_OFFSET) as i64;
+    require!(price > 0, CustError::InvalidOracle);
+    Ok(OraclePrice {
+        price,
+        conf: read_u64(data, AGG_CONF_OFFSET),
+        expo: read_u32(data, EXPO_OFFSET) as i32,
+        publish_time: read_u64(data, TIMESTAMP_OFFSET) as i64,
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
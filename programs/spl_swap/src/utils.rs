@@ -3,12 +3,11 @@ use fixed::types::I64F64;
 
 pub fn init_liquidity(amount_a:u64, amount_b:u64)->u64{
     // Computing the amount of liquidity about to be deposited
-    let liquidity = I64F64::from_num(amount_a)
+    I64F64::from_num(amount_a)
         .checked_mul(I64F64::from_num(amount_b))
         .unwrap()
         .sqrt()
-        .to_num::<u64>();
-    liquidity
+        .to_num::<u64>()
 }
 
 pub fn cacl_liquidity(total_supply:u64, amount_a: u64, pool_amount_a: u64, amount_b: u64, pool_amount_b:u64)->u64{
@@ -22,11 +21,10 @@ pub fn cacl_liquidity(total_supply:u64, amount_a: u64, pool_amount_a: u64, amoun
         ratio = ratio_b;
     }
 
-    let liquidity = I64F64::from_num(total_supply)
+    I64F64::from_num(total_supply)
             .checked_mul(I64F64::from_num(ratio))
             .unwrap()
-            .to_num::<u64>();
-    liquidity
+            .to_num::<u64>()
 }
 
 pub fn get_optimal_b(amount_a: u64, current_a: u64, current_b:u64) ->u64 {
@@ -35,11 +33,10 @@ pub fn get_optimal_b(amount_a: u64, current_a: u64, current_b:u64) ->u64 {
         .checked_div(I64F64::from_num(current_a))
         .unwrap();
 
-    let optimalb = I64F64::from_num(amount_a)
+    I64F64::from_num(amount_a)
             .checked_mul(ratio)
             .unwrap()
-            .to_num::<u64>();
-    optimalb
+            .to_num::<u64>()
 }
 
 pub fn get_optimal_a(amount_b: u64, current_a: u64, current_b:u64) ->u64 {
@@ -47,43 +44,320 @@ pub fn get_optimal_a(amount_b: u64, current_a: u64, current_b:u64) ->u64 {
         .checked_div(I64F64::from_num(current_b))
         .unwrap();
 
-    let optimala = I64F64::from_num(amount_b)
+    I64F64::from_num(amount_b)
             .checked_mul(ratio)
             .unwrap()
-            .to_num::<u64>();
-    optimala
+            .to_num::<u64>()
 }
 
 pub fn get_token_amount(liquidity:u64, total_liquidity:u64, amount: u64)->u64{
-    let ratio = I64F64::from_num(liquidity).checked_div(I64F64::from_num(total_liquidity + MIN_LIQUIDITY))
+    let ratio = I64F64::from_num(liquidity).checked_div(I64F64::from_num(total_liquidity))
     .unwrap();
 
-    let output = I64F64::from_num(amount).checked_mul(ratio)
-    .unwrap().to_num::<u64>();
-
-    output
+    I64F64::from_num(amount).checked_mul(ratio)
+    .unwrap().to_num::<u64>()
 }
 
-pub fn get_amount_b_out(amount: u64, current_amount_a: u64, current_amount_b:u64)->u64 {
-    // outputb = (inputa * current_amount_b*0.997)/(inputa*0.997+current_amount_a)
+pub fn get_amount_b_out(amount: u64, current_amount_a: u64, current_amount_b:u64, fee_bps: u16)->u64 {
+    // outputb = (inputa * current_amount_b*(1-fee))/(inputa*(1-fee)+current_amount_a)
+    let fee_factor = get_fee_factor(fee_bps);
     let mut numerator = I64F64::from_num(amount).checked_mul(I64F64::from_num(current_amount_b)).unwrap();
-    numerator = numerator.checked_mul(I64F64::from_num(0.997)).unwrap();
-    let mut denominator = I64F64::from_num(amount).checked_mul(I64F64::from_num(0.997)).unwrap();
-    denominator = denominator + I64F64::from_num(current_amount_a);
+    numerator = numerator.checked_mul(fee_factor).unwrap();
+    let mut denominator = I64F64::from_num(amount).checked_mul(fee_factor).unwrap();
+    denominator += I64F64::from_num(current_amount_a);
 
-    let output = I64F64::from_num(numerator).checked_div(denominator).unwrap().to_num::<u64>();
-    output
+    I64F64::from_num(numerator).checked_div(denominator).unwrap().to_num::<u64>()
 }
 
-pub fn get_amount_a_out(amount: u64, current_amount_a: u64, current_amount_b:u64)->u64 {
-    // outputa = (inputb * current_amount_a*0.997)/(inputb*0.997+current_amount_b)
+pub fn get_amount_a_out(amount: u64, current_amount_a: u64, current_amount_b:u64, fee_bps: u16)->u64 {
+    // outputa = (inputb * current_amount_a*(1-fee))/(inputb*(1-fee)+current_amount_b)
+    let fee_factor = get_fee_factor(fee_bps);
     let mut numerator = I64F64::from_num(amount).checked_mul(I64F64::from_num(current_amount_a)).unwrap();
-    numerator = numerator.checked_mul(I64F64::from_num(0.997)).unwrap();
-    let mut denominator = I64F64::from_num(amount).checked_mul(I64F64::from_num(0.997)).unwrap();
-    denominator = denominator + I64F64::from_num(current_amount_b);
+    numerator = numerator.checked_mul(fee_factor).unwrap();
+    let mut denominator = I64F64::from_num(amount).checked_mul(fee_factor).unwrap();
+    denominator += I64F64::from_num(current_amount_b);
+
+    I64F64::from_num(numerator).checked_div(denominator).unwrap().to_num::<u64>()
+}
+
+fn get_fee_factor(fee_bps: u16)->I64F64 {
+    I64F64::from_num(BPS_DENOMINATOR - fee_bps as u64)
+        .checked_div(I64F64::from_num(BPS_DENOMINATOR))
+        .unwrap()
+}
+
+pub fn get_zap_swap_amount(amount_in: u64, reserve_in: u64, fee_bps: u16)->u64 {
+    // portion of amount_in to swap so the remainder matches the post-swap pool ratio
+    // swap = reserve_in * (sqrt((1+g)^2 + 4*g*amount_in/reserve_in) - (1+g)) / (2*g), g = 1 - fee
+    let g = get_fee_factor(fee_bps);
+    let one_plus_g = I64F64::ONE + g;
+    let ratio = I64F64::from_num(amount_in)
+        .checked_div(I64F64::from_num(reserve_in))
+        .unwrap();
+    let inner = one_plus_g.checked_mul(one_plus_g).unwrap()
+        .checked_add(I64F64::from_num(4).checked_mul(g).unwrap().checked_mul(ratio).unwrap())
+        .unwrap();
+    let swap_amount = (inner.sqrt() - one_plus_g)
+        .checked_mul(I64F64::from_num(reserve_in))
+        .unwrap()
+        .checked_div(g * 2)
+        .unwrap()
+        .to_num::<u64>();
+    swap_amount.min(amount_in)
+}
+
+pub fn decay_linear(value: u64, elapsed: i64, period: i64)->u64 {
+    // value fades linearly to zero over period seconds
+    if elapsed <= 0 {
+        return value;
+    }
+    if elapsed >= period {
+        return 0;
+    }
+    (value as u128 * (period - elapsed) as u128 / period as u128) as u64
+}
+
+pub fn get_dynamic_fee_bps(base_fee_bps: u16, max_fee_bps: u16, variable_fee_control: u32, volatility_accumulator: u64)->u16 {
+    // fee = base + volatility * control / 10000, capped at max_fee_bps
+    let variable_fee = volatility_accumulator as u128 * variable_fee_control as u128 / BPS_DENOMINATOR as u128;
+    let fee = (base_fee_bps as u128).saturating_add(variable_fee);
+    fee.min(max_fee_bps as u128) as u16
+}
+
+pub fn get_price_move_bps(before_a: u64, before_b: u64, after_a: u64, after_b: u64)->u64 {
+    // |after_b/after_a - before_b/before_a| / (before_b/before_a) in bps
+    let before = before_b as u128 * after_a as u128;
+    let after = after_b as u128 * before_a as u128;
+    if before == 0 {
+        return 0;
+    }
+    let moved = after.abs_diff(before)
+        .checked_mul(BPS_DENOMINATOR as u128)
+        .map_or(u128::MAX, |moved| moved / before);
+    moved.min(u64::MAX as u128) as u64
+}
+
+pub fn get_flash_loan_fee(amount: u64, fee_bps: u16)->u64 {
+    // rounded up so small loans are never free
+    let fee = (amount as u128 * fee_bps as u128).div_ceil(BPS_DENOMINATOR as u128);
+    fee as u64
+}
 
-    let output = I64F64::from_num(numerator).checked_div(denominator).unwrap().to_num::<u64>();
-    output
+pub fn check_fee_adjusted_k(balance_a: u64, balance_b: u64, amount_in_a: u64, amount_in_b: u64, reserve_a: u64, reserve_b: u64, fee_bps: u16)->bool {
+    // (balance_a*10000 - in_a*fee) * (balance_b*10000 - in_b*fee) >= reserve_a*reserve_b*10000^2, as in Uniswap V2
+    let denominator = BPS_DENOMINATOR as u128;
+    let adjusted_a = balance_a as u128 * denominator - amount_in_a as u128 * fee_bps as u128;
+    let adjusted_b = balance_b as u128 * denominator - amount_in_b as u128 * fee_bps as u128;
+    let k = reserve_a as u128 * reserve_b as u128;
+    mul_wide(adjusted_a, adjusted_b) >= mul_wide(k, denominator * denominator)
+}
+
+pub fn check_lp_value(before: (u64, u64, u64), after: (u64, u64, u64))->bool {
+    // (reserve_a, reserve_b, supply): sqrt(a*b)/supply must not drop, compared squared as
+    // a'*b' * supply^2 >= a*b * supply'^2. nothing to protect while there is no supply
+    if before.2 == 0 {
+        return true;
+    }
+    let k_before = before.0 as u128 * before.1 as u128;
+    let k_after = after.0 as u128 * after.1 as u128;
+    let supply_before = before.2 as u128 * before.2 as u128;
+    let supply_after = after.2 as u128 * after.2 as u128;
+    mul_wide(k_after, supply_before) >= mul_wide(k_before, supply_after)
+}
+
+pub fn check_stable_lp_value(before: (u128, u64), after: (u128, u64))->bool {
+    // (D, supply): D/supply must not drop, compared as (D' + 1) * supply >= D * supply'.
+    // Newton's method only pins D down to one unit, hence the unit of slack
+    if before.1 == 0 {
+        return true;
+    }
+    mul_wide(after.0.saturating_add(1), before.1 as u128) >= mul_wide(before.0, after.1 as u128)
+}
+
+fn mul_wide(x: u128, y: u128)->(u128, u128) {
+    // full 256 bit product as (high, low) so products of u128 values can be compared
+    let mask = u64::MAX as u128;
+    let (x_hi, x_lo) = (x >> 64, x & mask);
+    let (y_hi, y_lo) = (y >> 64, y & mask);
+    let lo_lo = x_lo * y_lo;
+    let hi_lo = x_hi * y_lo;
+    let lo_hi = x_lo * y_hi;
+    let hi_hi = x_hi * y_hi;
+    let cross = (lo_lo >> 64) + (hi_lo & mask) + (lo_hi & mask);
+    let low = (cross << 64) | (lo_lo & mask);
+    let high = hi_hi + (hi_lo >> 64) + (lo_hi >> 64) + (cross >> 64);
+    (high, low)
+}
+
+pub fn get_fee_amount(amount: u64, fee_bps: u16)->u64 {
+    (amount as u128 * fee_bps as u128 / BPS_DENOMINATOR as u128) as u64
+}
+
+pub fn get_referral_amount(amount: u64, fee_bps: u16, referral_bps: u16)->u64 {
+    // referral_bps is a share of the input fee, not of the input
+    let fee = amount as u128 * fee_bps as u128 / BPS_DENOMINATOR as u128;
+    (fee * referral_bps as u128 / BPS_DENOMINATOR as u128) as u64
+}
+
+pub fn get_min_output(amount_in: u64, min_price: u64)->u64 {
+    // min_price is output per input scaled by PRICE_SCALE
+    let min_output = amount_in as u128 * min_price as u128 / PRICE_SCALE as u128;
+    min_output.min(u64::MAX as u128) as u64
+}
+
+pub fn get_batch_clearing(net_in: u64, matched_in: u64, reserve_in: u64, reserve_out: u64, fee_bps: u16)->(u64, u64) {
+    // the net seller side puts x into the pool, the matched side takes the rest of net_in.
+    // the largest x with out(x) * (net_in - x) >= x * matched_in makes the pool's average
+    // price out(x)/x equal to the uniform batch price matched_in/(net_in - x)
+    if matched_in == 0 {
+        return (net_in, get_amount_b_out(net_in, reserve_in, reserve_out, fee_bps));
+    }
+    let fits = |x: u64| {
+        let out = get_amount_b_out(x, reserve_in, reserve_out, fee_bps);
+        out as u128 * (net_in - x) as u128 >= x as u128 * matched_in as u128
+    };
+    let (mut lo, mut hi) = (0u64, net_in);
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if fits(mid) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    (lo, get_amount_b_out(lo, reserve_in, reserve_out, fee_bps))
+}
+
+pub fn get_spot_price(reserve_a: u64, reserve_b: u64)->u64 {
+    // b per a scaled by PRICE_SCALE
+    if reserve_a == 0 {
+        return 0;
+    }
+    let price = reserve_b as u128 * PRICE_SCALE as u128 / reserve_a as u128;
+    price.min(u64::MAX as u128) as u64
+}
+
+pub fn get_oracle_price(price: i64, expo: i32, decimals_a: u8, decimals_b: u8)->Option<u64> {
+    // oracle quotes whole b per whole a, converted to base units of b per base unit of a scaled by PRICE_SCALE
+    let exponent = expo + decimals_b as i32 - decimals_a as i32;
+    let scaled = (u64::try_from(price).ok()? as u128).checked_mul(PRICE_SCALE as u128)?;
+    let value = if exponent >= 0 {
+        scaled.checked_mul(10u128.checked_pow(exponent as u32)?)?
+    } else {
+        scaled / 10u128.checked_pow(exponent.unsigned_abs())?
+    };
+    u64::try_from(value).ok()
+}
+
+pub fn update_twap(twap_price: u64, spot_price: u64, elapsed: i64, window: i64)->u64 {
+    // moves towards spot by elapsed/window, a full window without trades catches up completely
+    if twap_price == 0 || elapsed >= window {
+        return spot_price;
+    }
+    if elapsed <= 0 {
+        return twap_price;
+    }
+    let moved = (spot_price as i128 - twap_price as i128) * elapsed as i128 / window as i128;
+    (twap_price as i128 + moved) as u64
+}
+
+pub fn cacl_liquidity_multi(total_supply:u64, amounts: &[u64], balances: &[u64])->(u64, Vec<u64>){
+    // the smallest deposit ratio over all vaults decides the LP share
+    let mut ratio = I64F64::MAX;
+    for (amount, balance) in amounts.iter().zip(balances.iter()) {
+        let ratio_i = I64F64::from_num(*amount)
+            .checked_div(I64F64::from_num(*balance))
+            .unwrap();
+        if ratio_i < ratio {
+            ratio = ratio_i;
+        }
+    }
+    // actual amounts are the balances scaled by the ratio, rounded up in favour of the pool
+    let acctual_amounts = amounts.iter().zip(balances.iter()).map(|(amount, balance)| {
+        let acctual = I64F64::from_num(*balance)
+            .checked_mul(ratio)
+            .unwrap()
+            .ceil()
+            .to_num::<u64>();
+        acctual.min(*amount)
+    }).collect();
+    let liquidity = I64F64::from_num(total_supply)
+            .checked_mul(ratio)
+            .unwrap()
+            .to_num::<u64>();
+    (liquidity, acctual_amounts)
+}
+
+pub fn stable_get_d(balances: &[u64], amp: u64)->Option<u128>{
+    // StableSwap invariant D, solved by Newton's method as in Curve:
+    // A*n*sum(x) + D = A*n*D + D^(n+1) / (n^n * prod(x))
+    let n = balances.len() as u128;
+    let sum = balances.iter().map(|x| *x as u128).sum::<u128>();
+    if sum == 0 {
+        return Some(0);
+    }
+    let ann = (amp as u128).checked_mul(n)?;
+    let mut d = sum;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        let mut d_p = d;
+        for x in balances {
+            d_p = d_p.checked_mul(d)?.checked_div((*x as u128).checked_mul(n)?)?;
+        }
+        let d_prev = d;
+        // d = (ann * sum + d_p * n) * d / ((ann - 1) * d + (n + 1) * d_p)
+        let numerator = ann.checked_mul(sum)?
+            .checked_add(d_p.checked_mul(n)?)?
+            .checked_mul(d)?;
+        let denominator = ann.checked_sub(1)?.checked_mul(d)?
+            .checked_add(n.checked_add(1)?.checked_mul(d_p)?)?;
+        d = numerator.checked_div(denominator)?;
+        if d.abs_diff(d_prev) <= 1 {
+            return Some(d);
+        }
+    }
+    None
+}
+
+pub fn stable_get_y(balances: &[u64], index_in: usize, index_out: usize, new_balance_in: u128, amp: u64, d: u128)->Option<u128>{
+    // balance of the output vault that keeps D unchanged once the input vault holds new_balance_in
+    let n = balances.len() as u128;
+    let ann = (amp as u128).checked_mul(n)?;
+    let mut c = d;
+    let mut sum = 0u128;
+    for (k, balance) in balances.iter().enumerate() {
+        let x = if k == index_in {
+            new_balance_in
+        } else if k == index_out {
+            continue;
+        } else {
+            *balance as u128
+        };
+        sum = sum.checked_add(x)?;
+        c = c.checked_mul(d)?.checked_div(x.checked_mul(n)?)?;
+    }
+    c = c.checked_mul(d)?.checked_div(ann.checked_mul(n)?)?;
+    let b = sum.checked_add(d.checked_div(ann)?)?;
+    let mut y = d;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        let y_prev = y;
+        // y = (y*y + c) / (2*y + b - d)
+        y = y.checked_mul(y)?.checked_add(c)?
+            .checked_div(y.checked_mul(2)?.checked_add(b)?.checked_sub(d)?)?;
+        if y.abs_diff(y_prev) <= 1 {
+            return Some(y);
+        }
+    }
+    None
+}
+
+pub fn get_stable_amount_out(amount: u64, index_in: usize, index_out: usize, balances: &[u64], amp: u64, fee_bps: u16)->Option<u64>{
+    // output = (balance_out - y - 1) * (1 - fee), the fee is charged on the output side
+    let d = stable_get_d(balances, amp)?;
+    let new_balance_in = (balances[index_in] as u128).checked_add(amount as u128)?;
+    let y = stable_get_y(balances, index_in, index_out, new_balance_in, amp, d)?;
+    let dy = (balances[index_out] as u128).checked_sub(y)?.saturating_sub(1);
+    let fee = dy.checked_mul(fee_bps as u128)?.checked_div(BPS_DENOMINATOR as u128)?;
+    u64::try_from(dy.checked_sub(fee)?).ok()
 }
 
 #[constant]
@@ -93,4 +367,225 @@ pub const MIN_LIQUIDITY: u64 = 1000;
 pub const MINT_SEED: &[u8] = b"LP_MINT";
 
 #[constant]
-pub const POOL_SEED: &[u8] = b"POOL";
\ No newline at end of file
+pub const POOL_SEED: &[u8] = b"POOL";
+
+#[constant]
+pub const MULTI_POOL_SEED: &[u8] = b"MULTI_POOL";
+
+#[constant]
+pub const ORDER_SEED: &[u8] = b"ORDER";
+
+#[constant]
+pub const DCA_SEED: &[u8] = b"DCA";
+
+#[constant]
+pub const BATCH_SEED: &[u8] = b"BATCH";
+
+#[constant]
+pub const INTENT_SEED: &[u8] = b"INTENT";
+
+// an unsettled batch lets its intents be refunded BATCH_EXPIRY_SLOTS slots after it closed
+#[constant]
+pub const BATCH_EXPIRY_SLOTS: u64 = 150;
+
+#[constant]
+pub const COMMIT_SEED: &[u8] = b"COMMIT";
+
+// a commit is revealable from COMMIT_MIN_SLOTS until COMMIT_EXPIRY_SLOTS slots after it landed
+#[constant]
+pub const COMMIT_MIN_SLOTS: u64 = 2;
+
+#[constant]
+pub const COMMIT_EXPIRY_SLOTS: u64 = 150;
+
+#[constant]
+pub const STATS_SEED: &[u8] = b"STATS";
+
+#[constant]
+pub const WITHDRAW_SEED: &[u8] = b"WITHDRAW";
+
+#[constant]
+pub const ALLOWLIST_SEED: &[u8] = b"ALLOWLIST";
+
+#[constant]
+pub const PRICE_SCALE: u64 = 1_000_000_000;
+
+// account sizes, shared by the instructions that create and migrate them
+pub const AMM_SPACE: usize = 8+32+1+2;
+
+pub const POOL_SPACE: usize = 8+32+32+32+1+1+2+2+4+8+8+8+8+8+2+1+1+8+1+8+8+8+2+1+1+32+8+2+2+2+8+8+8+8+8+1+1+1+1;
+
+pub const POOL_STATS_SPACE: usize = 8+32+16+16+16+16+8+8+8+8+1;
+
+// a Pool account shorter than this predates reserve tracking
+pub const POOL_RESERVES_END: usize = 8+32+32+32+1+1+2+2+4+8+8+8+8+8;
+
+// raised whenever Pool or Amm gain a field, migrate_pool brings older accounts up to it
+#[constant]
+pub const POOL_VERSION: u8 = 2;
+
+pub const MAX_MULTI_TOKENS: usize = 8;
+
+pub const MAX_BATCH_LEGS: usize = 32;
+
+#[constant]
+pub const MAX_AMP: u64 = 1_000_000;
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+#[constant]
+pub const DEFAULT_FEE_BPS: u16 = 30;
+
+#[constant]
+pub const MAX_FEE_BPS: u16 = 1000;
+
+#[constant]
+pub const MAX_VOLATILITY_ACCUMULATOR: u64 = 1_000_000;
+
+pub const STABLE_MAX_ITERATIONS: usize = 255;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AMP: u64 = 100;
+
+    fn swap_balances(balances: &[u64], index_in: usize, index_out: usize, amount: u64, fee_bps: u16)->(u64, Vec<u64>) {
+        let output = get_stable_amount_out(amount, index_in, index_out, balances, AMP, fee_bps).unwrap();
+        let mut after = balances.to_vec();
+        after[index_in] += amount;
+        after[index_out] -= output;
+        (output, after)
+    }
+
+    #[test]
+    fn stable_d_is_the_sum_for_a_balanced_pool() {
+        let d = stable_get_d(&[1_000_000, 1_000_000, 1_000_000], AMP).unwrap();
+        assert!(d.abs_diff(3_000_000) <= 1);
+        assert_eq!(stable_get_d(&[0, 0], AMP), Some(0));
+    }
+
+    #[test]
+    fn stable_d_does_not_drop_across_swaps() {
+        let mut balances = vec![1_000_000_000, 1_200_000_000, 900_000_000, 1_000_000_000];
+        let mut d = stable_get_d(&balances, AMP).unwrap();
+        for (index_in, index_out, amount) in [(0, 1, 50_000_000), (1, 2, 300_000_000), (3, 0, 1), (2, 3, 700_000_000), (1, 0, 12_345)] {
+            let (_, after) = swap_balances(&balances, index_in, index_out, amount, 0);
+            let d_after = stable_get_d(&after, AMP).unwrap();
+            assert!(d_after >= d, "D dropped from {} to {}", d, d_after);
+            // without a fee D only moves by rounding
+            assert!(d_after - d <= 4, "D moved from {} to {}", d, d_after);
+            balances = after;
+            d = d_after;
+        }
+    }
+
+    #[test]
+    fn stable_fee_grows_d() {
+        let balances = [1_000_000_000, 1_000_000_000];
+        let d = stable_get_d(&balances, AMP).unwrap();
+        let (_, after) = swap_balances(&balances, 0, 1, 100_000_000, DEFAULT_FEE_BPS);
+        assert!(stable_get_d(&after, AMP).unwrap() > d + 4);
+    }
+
+    #[test]
+    fn stable_output_is_non_increasing_in_the_fee() {
+        let balances = [5_000_000, 7_000_000, 6_000_000];
+        let mut previous = u64::MAX;
+        for fee_bps in (0..=MAX_FEE_BPS).step_by(10) {
+            let output = get_stable_amount_out(250_000, 0, 1, &balances, AMP, fee_bps).unwrap();
+            assert!(output <= previous, "output rose to {} at {} bps", output, fee_bps);
+            previous = output;
+        }
+    }
+
+    #[test]
+    fn stable_rounding_favours_the_pool() {
+        // a balanced pool never pays out more than it takes in, down to single units
+        let balances = [1_000_000_000, 1_000_000_000];
+        for amount in [1, 2, 10, 1_000, 1_000_000] {
+            let output = get_stable_amount_out(amount, 0, 1, &balances, MAX_AMP, 0).unwrap();
+            assert!(output < amount, "{} in paid {} out", amount, output);
+        }
+    }
+
+    #[test]
+    fn stable_math_overflow_returns_none() {
+        let balances = [u64::MAX; MAX_MULTI_TOKENS];
+        assert_eq!(stable_get_d(&balances, MAX_AMP), None);
+        assert_eq!(get_stable_amount_out(u64::MAX, 0, 1, &balances, MAX_AMP, 0), None);
+        // a swap on a dust pool rounds down to nothing
+        assert_eq!(get_stable_amount_out(1, 0, 1, &[1, 1], AMP, 0), Some(0));
+    }
+
+    #[test]
+    fn multi_liquidity_rounds_in_favour_of_the_pool() {
+        let balances = [1_000_003, 2_000_009, 2_999_999];
+        let supply = 6_000_000;
+        let (liquidity, acctual) = cacl_liquidity_multi(supply, &[333, 700, 1_000_000], &balances);
+        for (amount, balance) in acctual.iter().zip(balances.iter()) {
+            // deposited share of every vault covers the minted share of the supply
+            assert!(*amount as u128 * supply as u128 >= liquidity as u128 * *balance as u128);
+        }
+        assert!(acctual[0] <= 333 && acctual[1] <= 700);
+        // withdrawing what was minted returns no more than was deposited
+        for (amount, balance) in acctual.iter().zip(balances.iter()) {
+            assert!(get_token_amount(liquidity, supply + liquidity, balance + amount) <= *amount);
+        }
+    }
+
+    #[test]
+    fn batch_without_a_matched_side_trades_everything_with_the_pool() {
+        let (pool_in, pool_out) = get_batch_clearing(1_000, 0, 100_000, 100_000, DEFAULT_FEE_BPS);
+        assert_eq!(pool_in, 1_000);
+        assert_eq!(pool_out, get_amount_b_out(1_000, 100_000, 100_000, DEFAULT_FEE_BPS));
+    }
+
+    #[test]
+    fn batch_matched_at_spot_skips_the_pool() {
+        let (pool_in, pool_out) = get_batch_clearing(2_000, 1_000, 200_000, 100_000, DEFAULT_FEE_BPS);
+        assert_eq!((pool_in, pool_out), (0, 0));
+    }
+
+    #[test]
+    fn batch_clears_both_sides_at_one_price() {
+        let (net_in, matched_in) = (50_000u64, 20_000u64);
+        let (pool_in, pool_out) = get_batch_clearing(net_in, matched_in, 1_000_000, 1_000_000, DEFAULT_FEE_BPS);
+        assert!(pool_in > 0 && pool_in < net_in);
+        // pool_in is the largest amount whose pool price still reaches the batch price
+        let fits = |x: u64| get_amount_b_out(x, 1_000_000, 1_000_000, DEFAULT_FEE_BPS) as u128 * (net_in - x) as u128 >= x as u128 * matched_in as u128;
+        assert!(fits(pool_in) && !fits(pool_in + 1));
+        // net sellers get matched_in + pool_out for net_in, matched sellers get net_in - pool_in for matched_in,
+        // the two prices are reciprocal up to rounding
+        let net_price = (matched_in + pool_out) as f64 / net_in as f64;
+        let matched_price = (net_in - pool_in) as f64 / matched_in as f64;
+        assert!((net_price * matched_price - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn decay_linear_fades_to_zero_over_the_period() {
+        assert_eq!(decay_linear(1_000, 0, 100), 1_000);
+        assert_eq!(decay_linear(1_000, -5, 100), 1_000);
+        assert_eq!(decay_linear(1_000, 25, 100), 750);
+        assert_eq!(decay_linear(1_000, 100, 100), 0);
+        assert_eq!(decay_linear(1_000, 1_000, 100), 0);
+    }
+
+    #[test]
+    fn multi_liquidity_keeps_d_per_share() {
+        let amp = 100;
+        let balances = [1_000_003, 2_000_009, 2_999_999];
+        let supply = 6_000_000;
+        let d = stable_get_d(&balances, amp).unwrap();
+        let (liquidity, acctual) = cacl_liquidity_multi(supply, &[333, 700, 1_000_000], &balances);
+        let deposited: Vec<u64> = balances.iter().zip(acctual.iter()).map(|(balance, amount)| balance + amount).collect();
+        let d_deposited = stable_get_d(&deposited, amp).unwrap();
+        assert!(check_stable_lp_value((d, supply), (d_deposited, supply + liquidity)));
+
+        let burn = 1_234_567;
+        let withdrawn: Vec<u64> = balances.iter().map(|balance| balance - get_token_amount(burn, supply, *balance)).collect();
+        let d_withdrawn = stable_get_d(&withdrawn, amp).unwrap();
+        assert!(check_stable_lp_value((d, supply), (d_withdrawn, supply - burn)));
+        // minting without depositing dilutes the share
+        assert!(!check_stable_lp_value((d, supply), (d, supply + supply / 1_000)));
+    }
+}
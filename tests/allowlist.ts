@@ -0,0 +1,136 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { randomBytes } from "crypto";
+import { expect } from "chai";
+
+describe("allowlist", () => {
+  let testBase: TestBase;
+  const balance = async (account) => BigInt((await testBase.getTAInfo(account)).amount);
+  const expect_error = async (promise: Promise<any>, error: string) => {
+    try {
+      await promise;
+      expect.fail(`should fail with ${error}`);
+    } catch (e) {
+      expect(String(e)).to.contain(error);
+    }
+  };
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(10000), new BN(10000), new BN(1000), new BN(1000));
+    await testBase.set_permissioned(testBase.payer, true);
+  });
+
+  it("rejects depositors that are not allowlisted", async () => {
+    await expect_error(testBase.swap(testBase.user1, new BN(100), new BN(0), true), "NotAllowlisted");
+    await expect_error(testBase.add_liquidity(testBase.user1, new BN(1000), new BN(1000), new BN(100), new BN(100)), "NotAllowlisted");
+    await expect_error(testBase.remove_liquidity(testBase.user0, new BN(0), new BN(0), new BN(1000)), "NotAllowlisted");
+
+    const borrow = await testBase.flash_borrow_ix(testBase.user1, new BN(1000), true, testBase.user1_token0_account);
+    const repay = await testBase.flash_repay_ix(testBase.user1, testBase.user1_token0_account);
+    await expect_error(testBase.send([borrow, repay], [testBase.user1]), "NotAllowlisted");
+  });
+
+  it("accepts depositors once they are allowlisted", async () => {
+    await testBase.add_to_allowlist(testBase.payer, testBase.user0.publicKey);
+    await testBase.add_to_allowlist(testBase.payer, testBase.user1.publicKey);
+
+    const token1_before = await balance(testBase.user1_token1_account);
+    await testBase.swap(testBase.user1, new BN(100), new BN(0), true);
+    expect((await balance(testBase.user1_token1_account)) > token1_before).to.be.true;
+
+    await testBase.add_liquidity(testBase.user1, new BN(1000), new BN(1000), new BN(100), new BN(100));
+    expect((await balance(testBase.get_depositor_lp_account(testBase.user1))) > BigInt(0)).to.be.true;
+
+    const lp_before = await balance(testBase.get_depositor_lp_account(testBase.user0));
+    await testBase.remove_liquidity(testBase.user0, new BN(0), new BN(0), new BN(1000));
+    expect(lp_before - (await balance(testBase.get_depositor_lp_account(testBase.user0)))).to.equal(BigInt(1000));
+
+    const borrow = await testBase.flash_borrow_ix(testBase.user1, new BN(1000), true, testBase.user1_token0_account);
+    const repay = await testBase.flash_repay_ix(testBase.user1, testBase.user1_token0_account);
+    await testBase.send([borrow, repay], [testBase.user1]);
+  });
+
+  it("rejects a depositor again after removal", async () => {
+    await testBase.remove_from_allowlist(testBase.payer, testBase.user1.publicKey);
+    await expect_error(testBase.swap(testBase.user1, new BN(100), new BN(0), true), "NotAllowlisted");
+  });
+});
+
+describe("allowlist, escrows placed before the gate", () => {
+  let testBase: TestBase;
+  let batch_slot: BN;
+  // COMMIT_EXPIRY_SLOTS / BATCH_EXPIRY_SLOTS in utils.rs
+  const expiry_slots = BigInt(150);
+  const salt = randomBytes(32);
+  const balance = async (account) => BigInt((await testBase.getTAInfo(account)).amount);
+  const current_slot = (): bigint => BigInt(testBase.client.getClock().slot);
+  const expect_error = async (promise: Promise<any>, error: string) => {
+    try {
+      await promise;
+      expect.fail(`should fail with ${error}`);
+    } catch (e) {
+      expect(String(e)).to.contain(error);
+    }
+  };
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(10000), new BN(10000), new BN(1000), new BN(1000));
+
+    // user1 is allowlisted while it places, then the pool is gated and user1 removed
+    await testBase.add_to_allowlist(testBase.payer, testBase.user1.publicKey);
+    await testBase.place_order(testBase.user1, new BN(1), true, new BN(1000), new BN(1), new BN(10));
+    await testBase.create_dca(testBase.user1, new BN(1), true, new BN(1000), new BN(500), new BN(60), new BN(0), new BN(0));
+    await testBase.commit_swap(testBase.user1, new BN(1), new BN(1000), true, testBase.get_commitment(new BN(0), salt));
+    await testBase.set_batch_auction(testBase.payer, true);
+    batch_slot = new BN(current_slot().toString());
+    await testBase.queue_swap(testBase.user1, batch_slot, new BN(1000), true);
+    await testBase.set_batch_auction(testBase.payer, false);
+    await testBase.set_permissioned(testBase.payer, true);
+    await testBase.remove_from_allowlist(testBase.payer, testBase.user1.publicKey);
+    testBase.warp_to_slot(current_slot() + BigInt(2));
+  });
+
+  it("does not execute orders, DCAs, commits or batches on a permissioned pool", async () => {
+    await expect_error(testBase.fill_order(testBase.user0, testBase.user1.publicKey, new BN(1), testBase.user0_token0_account), "PermissionedPool");
+    await expect_error(testBase.execute_dca_slice(testBase.user0, testBase.user1.publicKey, new BN(1), testBase.user0_token0_account), "PermissionedPool");
+    await expect_error(testBase.reveal_swap(testBase.user1, new BN(1), true, new BN(0), salt), "PermissionedPool");
+    await expect_error(testBase.settle_batch(testBase.payer, batch_slot), "PermissionedPool");
+  });
+
+  it("still refunds them to the owner", async () => {
+    // the order escrow holds its tip as well
+    const before = await balance(testBase.user1_token0_account);
+    await testBase.cancel_order(testBase.user1, new BN(1));
+    await testBase.cancel_dca(testBase.user1, new BN(1));
+    testBase.warp_to_slot(current_slot() + expiry_slots + BigInt(1));
+    await testBase.refund_commit(testBase.user1, new BN(1), true);
+    await testBase.refund_intent(testBase.user1, batch_slot, true);
+    expect((await balance(testBase.user1_token0_account)) - before).to.equal(BigInt(4010));
+  });
+});
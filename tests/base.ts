@@ -5,10 +5,13 @@ import {
   PublicKey,
   SystemProgram,
   Transaction,
+  TransactionInstruction,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
   sendAndConfirmTransaction,
   Connection
 } from "@solana/web3.js";
 import { Program, BN } from "@coral-xyz/anchor";
+import { createHash } from "crypto";
 import { SplSwap } from "../target/types/spl_swap";
 import {
   MINT_SIZE,
@@ -17,6 +20,9 @@ import {
   TOKEN_PROGRAM_ID,
   ASSOCIATED_TOKEN_PROGRAM_ID,
   createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  ExtensionType,
+  getMintLen,
   createMintToInstruction,
   getAssociatedTokenAddressSync,
   createAssociatedTokenAccountInstruction,
@@ -41,9 +47,15 @@ export class TestBase {
   public user0_token1_account: PublicKey;
   public pool_token0_account: PublicKey;
   public pool_token1_account: PublicKey;
+  public pool_lp_account: PublicKey;
   public amm: PublicKey;
   public pool_pda: PublicKey;
   public mint_lp_pda: PublicKey;
+  public pool_stats_pda: PublicKey;
+  public oracle: PublicKey | null = null;
+  // token programs owning token0 / token1, a test switches one to Token-2022 before creating its mint
+  public token0_program: PublicKey = TOKEN_PROGRAM_ID;
+  public token1_program: PublicKey = TOKEN_PROGRAM_ID;
 
   public user1_token0_account: PublicKey;
   public user1_token1_account: PublicKey;
@@ -84,19 +96,21 @@ export class TestBase {
     return transactionSignature;
   }
 
-  async create_pool(payer: Keypair, admin: PublicKey):Promise<string> {
+  async create_pool(payer: Keypair, admin: PublicKey, fee_on_transfer_a: boolean = false, fee_on_transfer_b: boolean = false):Promise<string> {
     const [pool_pda] = this.get_pool_pda(admin);
     const [mint_LP_pda] = this.get_mint_lp_pda(admin);
     this.mint_lp_pda = mint_LP_pda;
     this.pool_pda = pool_pda;
+    [this.pool_stats_pda] = this.get_pool_stats_pda();
 
-    const transactionSignature = await this.swapProgram.methods.createPool().accountsPartial({
+    const transactionSignature = await this.swapProgram.methods.createPool(fee_on_transfer_a, fee_on_transfer_b).accountsPartial({
       admin: payer.publicKey,
       systemProgram: SystemProgram.programId,
       mintA: this.token0_mint.publicKey,
       mintB: this.token1_mint.publicKey,
       amm: this.amm,
       poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
       mintLiquidity: mint_LP_pda,
       tokenProgram: TOKEN_PROGRAM_ID,
     }).signers([payer]).rpc();
@@ -108,7 +122,7 @@ export class TestBase {
         this.token0_mint.publicKey,
         this.pool_pda,
         true, // allowOwnerOffCurve
-        TOKEN_PROGRAM_ID,
+        this.token0_program,
         ASSOCIATED_TOKEN_PROGRAM_ID
     );
 
@@ -116,11 +130,18 @@ export class TestBase {
         this.token1_mint.publicKey,
         this.pool_pda,
         true, // allowOwnerOffCurve
-        TOKEN_PROGRAM_ID,
+        this.token1_program,
         ASSOCIATED_TOKEN_PROGRAM_ID
     );
     this.pool_token0_account = pool_token0_account;
     this.pool_token1_account = pool_token1_account;
+    this.pool_lp_account = getAssociatedTokenAddressSync(
+        this.mint_lp_pda,
+        this.pool_pda,
+        true, // allowOwnerOffCurve
+        TOKEN_PROGRAM_ID,
+        ASSOCIATED_TOKEN_PROGRAM_ID
+    );
 
     const transactionSignature = await this.swapProgram.methods.createPoolToken().accountsPartial(
       {
@@ -129,16 +150,35 @@ export class TestBase {
         mintA: this.token0_mint.publicKey,
         mintB: this.token1_mint.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgramA: this.token0_program,
+        tokenProgramB: this.token1_program,
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
         amm: this.amm,
         poolAccount: this.pool_pda,
         poolAccountA: pool_token0_account,
         poolAccountB: pool_token1_account,
+        mintLiquidity: this.mint_lp_pda,
+        poolAccountLiquidity: this.pool_lp_account,
       }
     ).signers([this.payer]).rpc()
     return transactionSignature;
   }
 
+  async migrate_pool(admin: Keypair): Promise<string> {
+    return await this.swapProgram.methods.migratePool().accountsPartial({
+      admin: admin.publicKey,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      amm: this.amm,
+      poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
+      mintLiquidity: this.mint_lp_pda,
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      poolAccountLiquidity: this.pool_lp_account,
+    }).signers([admin]).rpc();
+  }
+
   get_depositor_lp_account(depositor:Keypair): PublicKey{
     return getAssociatedTokenAddressSync(
         this.mint_lp_pda,
@@ -149,7 +189,7 @@ export class TestBase {
     );
   }
 
-  async add_liquidity(depositor: Keypair, amount_a: BN, amount_b: BN, min_amount_a: BN, min_amount_b: BN): Promise<string>{
+  async add_liquidity(depositor: Keypair, amount_a: BN, amount_b: BN, min_amount_a: BN, min_amount_b: BN, min_lp_out: BN = new BN(0)): Promise<string>{
 
     const depositor_lp_account = getAssociatedTokenAddressSync(
         this.mint_lp_pda,
@@ -163,7 +203,7 @@ export class TestBase {
         this.token0_mint.publicKey,
         depositor.publicKey,
         false, // allowOwnerOffCurve
-        TOKEN_PROGRAM_ID,
+        this.token0_program,
         ASSOCIATED_TOKEN_PROGRAM_ID
       );
 
@@ -171,25 +211,30 @@ export class TestBase {
       this.token1_mint.publicKey,
       depositor.publicKey,
       false, // allowOwnerOffCurve
-      TOKEN_PROGRAM_ID,
+      this.token1_program,
       ASSOCIATED_TOKEN_PROGRAM_ID
     );
 
 
     // 构建交易指令而不是直接执行
-    const instruction = await this.swapProgram.methods.addLiquidity(amount_a, amount_b, min_amount_a, min_amount_b).accountsPartial({
+    const instruction = await this.swapProgram.methods.addLiquidity(amount_a, amount_b, min_amount_a, min_amount_b, min_lp_out).accountsPartial({
 
       poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
       depositor: depositor.publicKey,
       mintA: this.token0_mint.publicKey,
       mintB: this.token1_mint.publicKey,
       mintLiquidity: this.mint_lp_pda,
       poolAccountA: this.pool_token0_account,
       poolAccountB: this.pool_token1_account,
+      poolAccountLiquidity: this.pool_lp_account,
       depositorAccountA: depositorAccountA,
       depositorAccountB: depositorAccountB,
       depositorAccountLiquidity: depositor_lp_account,
+      allowlist: this.get_allowlist(depositor.publicKey),
       tokenProgram: TOKEN_PROGRAM_ID,
+      tokenProgramA: this.token0_program,
+      tokenProgramB: this.token1_program,
       systemProgram: SystemProgram.programId,
       associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
     }).instruction();
@@ -200,7 +245,7 @@ export class TestBase {
     return transactionSignature;
   }
 
-  async remove_liquidity(depositor: Keypair, min_amount_a: BN, min_amount_b: BN, liquidity: BN): Promise<string> {
+  async remove_liquidity(depositor: Keypair, min_amount_a: BN, min_amount_b: BN, liquidity: BN, withdraw_request: PublicKey | null = null): Promise<string> {
     const depositor_lp_account = getAssociatedTokenAddressSync(
         this.mint_lp_pda,
         depositor.publicKey,
@@ -213,7 +258,7 @@ export class TestBase {
         this.token0_mint.publicKey,
         depositor.publicKey,
         false, // allowOwnerOffCurve
-        TOKEN_PROGRAM_ID,
+        this.token0_program,
         ASSOCIATED_TOKEN_PROGRAM_ID
       );
     
@@ -221,13 +266,14 @@ export class TestBase {
       this.token1_mint.publicKey,
       depositor.publicKey,
       false, // allowOwnerOffCurve
-      TOKEN_PROGRAM_ID,
+      this.token1_program,
       ASSOCIATED_TOKEN_PROGRAM_ID
     );
 
     // 构建交易指令而不是直接执行
     const instruction = await this.swapProgram.methods.removeLiquidity(liquidity, min_amount_a, min_amount_b).accountsPartial({
       poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
       depositor: depositor.publicKey,
       mintA: this.token0_mint.publicKey,
       mintB: this.token1_mint.publicKey, 
@@ -236,7 +282,12 @@ export class TestBase {
       poolAccountB: this.pool_token1_account,
       depositorAccountA: depositorAccountA,
       depositorAccountB: depositorAccountB,
-      depositorAccountLiquidity: depositor_lp_account
+      depositorAccountLiquidity: depositor_lp_account,
+      withdrawRequest: withdraw_request,
+      withdrawEscrow: withdraw_request ? getAssociatedTokenAddressSync(this.mint_lp_pda, withdraw_request, true) : null,
+      allowlist: this.get_allowlist(depositor.publicKey),
+      tokenProgramA: this.token0_program,
+      tokenProgramB: this.token1_program,
     }).instruction();
 
     // 创建交易并使用 provider.sendAndConfirm
@@ -245,12 +296,12 @@ export class TestBase {
     return transactionSignature;
   }
 
-  async swap(depositor: Keypair, amount: BN, min_output: BN, output_b: boolean): Promise<string> {
+  async swap_ix(depositor: Keypair, amount: BN, min_output: BN, output_b: boolean, max_price_impact_bps: number | null = null, referral_bps: number = 0, referrer_account: PublicKey | null = null): Promise<TransactionInstruction> {
     const depositorAccountA = getAssociatedTokenAddressSync(
         this.token0_mint.publicKey,
         depositor.publicKey,
         false, // allowOwnerOffCurve
-        TOKEN_PROGRAM_ID,
+        this.token0_program,
         ASSOCIATED_TOKEN_PROGRAM_ID
       );
     
@@ -258,27 +309,562 @@ export class TestBase {
       this.token1_mint.publicKey,
       depositor.publicKey,
       false, // allowOwnerOffCurve
-      TOKEN_PROGRAM_ID,
+      this.token1_program,
       ASSOCIATED_TOKEN_PROGRAM_ID
     );
 
-    // 构建交易指令而不是直接执行
-    const instruction = await this.swapProgram.methods.swapExtacttokenFortoken(amount, min_output, output_b).accountsPartial({
+    // the referrer token account is the only remaining account
+    const remainingAccounts = referrer_account ? [{ pubkey: referrer_account, isWritable: true, isSigner: false }] : [];
+    return await this.swapProgram.methods.swapExtacttokenFortoken(amount, min_output, output_b, max_price_impact_bps, referral_bps).accountsPartial({
       poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
+      amm: this.amm,
       mintA: this.token0_mint.publicKey,
       mintB: this.token1_mint.publicKey,
       depositor: depositor.publicKey,
       poolAccountA: this.pool_token0_account,
       poolAccountB: this.pool_token1_account,
       depositorAccountA: depositorAccountA,
-      depositorAccountB: depositorAccountB
-    }).instruction();
+      depositorAccountB: depositorAccountB,
+      oracle: this.oracle,
+      allowlist: this.get_allowlist(depositor.publicKey),
+      tokenProgramA: this.token0_program,
+      tokenProgramB: this.token1_program,
+    }).remainingAccounts(remainingAccounts).instruction();
+  }
+
+  async swap(depositor: Keypair, amount: BN, min_output: BN, output_b: boolean, max_price_impact_bps: number | null = null, referral_bps: number = 0, referrer_account: PublicKey | null = null): Promise<string> {
+    const instruction = await this.swap_ix(depositor, amount, min_output, output_b, max_price_impact_bps, referral_bps, referrer_account);
 
     // 创建交易并使用 provider.sendAndConfirm
     const transaction = new Transaction().add(instruction);
     const transactionSignature = await this.provider.sendAndConfirm(transaction, [depositor]);
     return transactionSignature;
-    
+  }
+
+  // the receiver program is the first remaining account, the callback's accounts follow it
+  async flash_swap_ix(depositor: Keypair, amount_out: BN, output_b: boolean, callback: TransactionInstruction): Promise<TransactionInstruction> {
+    return await this.swapProgram.methods.flashSwap(amount_out, output_b, Buffer.from(callback.data)).accountsPartial({
+      poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
+      amm: this.amm,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      depositor: depositor.publicKey,
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      depositorAccountA: getAssociatedTokenAddressSync(this.token0_mint.publicKey, depositor.publicKey, false, this.token0_program),
+      depositorAccountB: getAssociatedTokenAddressSync(this.token1_mint.publicKey, depositor.publicKey, false, this.token1_program),
+      oracle: this.oracle,
+      allowlist: this.get_allowlist(depositor.publicKey),
+      tokenProgramA: this.token0_program,
+      tokenProgramB: this.token1_program,
+    }).remainingAccounts([
+      { pubkey: callback.programId, isWritable: false, isSigner: false },
+      ...callback.keys,
+    ]).instruction();
+  }
+
+  get_order_pda(owner: PublicKey, order_id: BN): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync([this.pool_pda.toBuffer(), owner.toBuffer(), order_id.toArrayLike(Buffer, "le", 8), Buffer.from("ORDER")], this.swapProgram.programId);
+  }
+
+  async place_order(owner: Keypair, order_id: BN, sell_a: boolean, amount_in: BN, min_output: BN, tip: BN): Promise<string> {
+    const [input_mint, output_mint] = sell_a ? [this.token0_mint.publicKey, this.token1_mint.publicKey] : [this.token1_mint.publicKey, this.token0_mint.publicKey];
+    const [order] = this.get_order_pda(owner.publicKey, order_id);
+    return await this.swapProgram.methods.placeOrder(order_id, amount_in, min_output, tip).accountsPartial({
+      owner: owner.publicKey,
+      poolAccount: this.pool_pda,
+      inputMint: input_mint,
+      outputMint: output_mint,
+      order,
+      escrow: getAssociatedTokenAddressSync(input_mint, order, true),
+      ownerAccountIn: getAssociatedTokenAddressSync(input_mint, owner.publicKey),
+      ownerAccountOut: getAssociatedTokenAddressSync(output_mint, owner.publicKey),
+    }).signers([owner]).rpc();
+  }
+
+  async fill_order(filler: Keypair, owner: PublicKey, order_id: BN, filler_account: PublicKey): Promise<string> {
+    const [order] = this.get_order_pda(owner, order_id);
+    const order_account = await this.swapProgram.account.limitOrder.fetch(order);
+    return await this.swapProgram.methods.fillOrder().accountsPartial({
+      filler: filler.publicKey,
+      poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      order,
+      owner,
+      escrow: getAssociatedTokenAddressSync(order_account.inputMint, order, true),
+      ownerAccountOut: getAssociatedTokenAddressSync(order_account.outputMint, owner),
+      fillerAccount: filler_account,
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      oracle: this.oracle,
+    }).signers([filler]).rpc();
+  }
+
+  async cancel_order(owner: Keypair, order_id: BN): Promise<string> {
+    const [order] = this.get_order_pda(owner.publicKey, order_id);
+    const order_account = await this.swapProgram.account.limitOrder.fetch(order);
+    return await this.swapProgram.methods.cancelOrder().accountsPartial({
+      owner: owner.publicKey,
+      order,
+      escrow: getAssociatedTokenAddressSync(order_account.inputMint, order, true),
+      ownerAccountIn: getAssociatedTokenAddressSync(order_account.inputMint, owner.publicKey),
+    }).signers([owner]).rpc();
+  }
+
+  get_dca_pda(owner: PublicKey, dca_id: BN): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync([this.pool_pda.toBuffer(), owner.toBuffer(), dca_id.toArrayLike(Buffer, "le", 8), Buffer.from("DCA")], this.swapProgram.programId);
+  }
+
+  async create_dca(owner: Keypair, dca_id: BN, sell_a: boolean, amount: BN, slice_amount: BN, interval: BN, min_price: BN, keeper_reward: BN): Promise<string> {
+    const [input_mint, output_mint] = sell_a ? [this.token0_mint.publicKey, this.token1_mint.publicKey] : [this.token1_mint.publicKey, this.token0_mint.publicKey];
+    const [dca] = this.get_dca_pda(owner.publicKey, dca_id);
+    return await this.swapProgram.methods.createDca(dca_id, amount, slice_amount, interval, min_price, keeper_reward).accountsPartial({
+      owner: owner.publicKey,
+      poolAccount: this.pool_pda,
+      inputMint: input_mint,
+      outputMint: output_mint,
+      dca,
+      escrow: getAssociatedTokenAddressSync(input_mint, dca, true),
+      ownerAccountIn: getAssociatedTokenAddressSync(input_mint, owner.publicKey),
+      ownerAccountOut: getAssociatedTokenAddressSync(output_mint, owner.publicKey),
+    }).signers([owner]).rpc();
+  }
+
+  async cancel_dca(owner: Keypair, dca_id: BN): Promise<string> {
+    const [dca] = this.get_dca_pda(owner.publicKey, dca_id);
+    const dca_account = await this.swapProgram.account.dcaOrder.fetch(dca);
+    return await this.swapProgram.methods.cancelDca().accountsPartial({
+      owner: owner.publicKey,
+      dca,
+      escrow: getAssociatedTokenAddressSync(dca_account.inputMint, dca, true),
+      ownerAccountIn: getAssociatedTokenAddressSync(dca_account.inputMint, owner.publicKey),
+    }).signers([owner]).rpc();
+  }
+
+  async execute_dca_slice(keeper: Keypair, owner: PublicKey, dca_id: BN, keeper_account: PublicKey): Promise<string> {
+    const [dca] = this.get_dca_pda(owner, dca_id);
+    const dca_account = await this.swapProgram.account.dcaOrder.fetch(dca);
+    return await this.swapProgram.methods.executeDcaSlice().accountsPartial({
+      keeper: keeper.publicKey,
+      poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      dca,
+      escrow: getAssociatedTokenAddressSync(dca_account.inputMint, dca, true),
+      ownerAccountOut: getAssociatedTokenAddressSync(dca_account.outputMint, owner),
+      keeperAccount: keeper_account,
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      oracle: this.oracle,
+    }).signers([keeper]).rpc();
+  }
+
+  // moves the clock forward and rolls the blockhash so a repeated transaction is not deduplicated
+  warp(seconds: number): void {
+    const clock = this.client.getClock();
+    clock.unixTimestamp += BigInt(seconds);
+    this.client.setClock(clock);
+    this.client.expireBlockhash();
+  }
+
+  async set_batch_auction(admin: Keypair, enabled: boolean): Promise<string> {
+    return await this.swapProgram.methods.setBatchAuction(enabled).accountsPartial({
+      admin: admin.publicKey,
+      amm: this.amm,
+      poolAccount: this.pool_pda,
+    }).signers([admin]).rpc();
+  }
+
+  get_batch_pda(slot: BN): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync([this.pool_pda.toBuffer(), slot.toArrayLike(Buffer, "le", 8), Buffer.from("BATCH")], this.swapProgram.programId);
+  }
+
+  get_intent_pda(batch: PublicKey, owner: PublicKey): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync([batch.toBuffer(), owner.toBuffer(), Buffer.from("INTENT")], this.swapProgram.programId);
+  }
+
+  // token0 / token1 accounts of the batch escrow
+  get_batch_accounts(batch: PublicKey): [PublicKey, PublicKey] {
+    return [
+      getAssociatedTokenAddressSync(this.token0_mint.publicKey, batch, true),
+      getAssociatedTokenAddressSync(this.token1_mint.publicKey, batch, true),
+    ];
+  }
+
+  async queue_swap(owner: Keypair, slot: BN, amount_in: BN, sell_a: boolean): Promise<string> {
+    const [batch] = this.get_batch_pda(slot);
+    const [batch_account_a, batch_account_b] = this.get_batch_accounts(batch);
+    return await this.swapProgram.methods.queueSwap(slot, amount_in, sell_a).accountsPartial({
+      owner: owner.publicKey,
+      poolAccount: this.pool_pda,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      batch,
+      intent: this.get_intent_pda(batch, owner.publicKey)[0],
+      batchAccountA: batch_account_a,
+      batchAccountB: batch_account_b,
+      ownerAccountIn: getAssociatedTokenAddressSync(sell_a ? this.token0_mint.publicKey : this.token1_mint.publicKey, owner.publicKey),
+    }).signers([owner]).rpc();
+  }
+
+  async settle_batch(settler: Keypair, slot: BN): Promise<string> {
+    const [batch] = this.get_batch_pda(slot);
+    const [batch_account_a, batch_account_b] = this.get_batch_accounts(batch);
+    return await this.swapProgram.methods.settleBatch().accountsPartial({
+      settler: settler.publicKey,
+      poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      batch,
+      batchAccountA: batch_account_a,
+      batchAccountB: batch_account_b,
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      oracle: this.oracle,
+    }).signers([settler]).rpc();
+  }
+
+  async claim_batch(owner: Keypair, slot: BN, sell_a: boolean): Promise<string> {
+    const [batch] = this.get_batch_pda(slot);
+    const [batch_account_a, batch_account_b] = this.get_batch_accounts(batch);
+    return await this.swapProgram.methods.claimBatch().accountsPartial({
+      owner: owner.publicKey,
+      batch,
+      poolAccount: this.pool_pda,
+      intent: this.get_intent_pda(batch, owner.publicKey)[0],
+      batchAccountA: batch_account_a,
+      batchAccountB: batch_account_b,
+      ownerAccountOut: getAssociatedTokenAddressSync(sell_a ? this.token1_mint.publicKey : this.token0_mint.publicKey, owner.publicKey),
+    }).signers([owner]).rpc();
+  }
+
+  async refund_intent(owner: Keypair, slot: BN, sell_a: boolean): Promise<string> {
+    const [batch] = this.get_batch_pda(slot);
+    const [batch_account_a, batch_account_b] = this.get_batch_accounts(batch);
+    return await this.swapProgram.methods.refundIntent().accountsPartial({
+      owner: owner.publicKey,
+      batch,
+      poolAccount: this.pool_pda,
+      intent: this.get_intent_pda(batch, owner.publicKey)[0],
+      batchAccountA: batch_account_a,
+      batchAccountB: batch_account_b,
+      ownerAccountIn: getAssociatedTokenAddressSync(sell_a ? this.token0_mint.publicKey : this.token1_mint.publicKey, owner.publicKey),
+    }).signers([owner]).rpc();
+  }
+
+  get_commit_pda(owner: PublicKey, commit_id: BN): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync([this.pool_pda.toBuffer(), owner.toBuffer(), commit_id.toArrayLike(Buffer, "le", 8), Buffer.from("COMMIT")], this.swapProgram.programId);
+  }
+
+  // sha256(min_output le, salt), matching reveal_swap
+  get_commitment(min_output: BN, salt: Buffer): number[] {
+    return Array.from(createHash("sha256").update(Buffer.concat([min_output.toArrayLike(Buffer, "le", 8), salt])).digest());
+  }
+
+  async commit_swap(owner: Keypair, commit_id: BN, amount_in: BN, sell_a: boolean, commitment: number[]): Promise<string> {
+    const input_mint = sell_a ? this.token0_mint.publicKey : this.token1_mint.publicKey;
+    const [commit] = this.get_commit_pda(owner.publicKey, commit_id);
+    return await this.swapProgram.methods.commitSwap(commit_id, amount_in, commitment).accountsPartial({
+      owner: owner.publicKey,
+      poolAccount: this.pool_pda,
+      inputMint: input_mint,
+      commit,
+      escrow: getAssociatedTokenAddressSync(input_mint, commit, true),
+      ownerAccountIn: getAssociatedTokenAddressSync(input_mint, owner.publicKey),
+    }).signers([owner]).rpc();
+  }
+
+  async reveal_swap(owner: Keypair, commit_id: BN, sell_a: boolean, min_output: BN, salt: Buffer): Promise<string> {
+    const input_mint = sell_a ? this.token0_mint.publicKey : this.token1_mint.publicKey;
+    const [commit] = this.get_commit_pda(owner.publicKey, commit_id);
+    return await this.swapProgram.methods.revealSwap(min_output, Array.from(salt)).accountsPartial({
+      owner: owner.publicKey,
+      poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      commit,
+      escrow: getAssociatedTokenAddressSync(input_mint, commit, true),
+      ownerAccountOut: getAssociatedTokenAddressSync(sell_a ? this.token1_mint.publicKey : this.token0_mint.publicKey, owner.publicKey),
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      oracle: this.oracle,
+    }).signers([owner]).rpc();
+  }
+
+  async refund_commit(owner: Keypair, commit_id: BN, sell_a: boolean): Promise<string> {
+    const input_mint = sell_a ? this.token0_mint.publicKey : this.token1_mint.publicKey;
+    const [commit] = this.get_commit_pda(owner.publicKey, commit_id);
+    return await this.swapProgram.methods.refundCommit().accountsPartial({
+      owner: owner.publicKey,
+      commit,
+      escrow: getAssociatedTokenAddressSync(input_mint, commit, true),
+      ownerAccountIn: getAssociatedTokenAddressSync(input_mint, owner.publicKey),
+    }).signers([owner]).rpc();
+  }
+
+  // advances to the given slot, the blockhash rolls with it
+  warp_to_slot(slot: bigint): void {
+    this.client.warpToSlot(slot);
+    this.client.expireBlockhash();
+  }
+
+  async send(instructions: TransactionInstruction[], signers: Keypair[]): Promise<string> {
+    const transaction = new Transaction().add(...instructions);
+    return await this.provider.sendAndConfirm(transaction, signers);
+  }
+
+  async get_pool(): Promise<any> {
+    return await this.swapProgram.account.pool.fetch(this.pool_pda);
+  }
+
+  async set_max_referral_bps(admin: Keypair, max_referral_bps: number): Promise<string> {
+    return await this.swapProgram.methods.setMaxReferralBps(max_referral_bps).accountsPartial({
+      admin: admin.publicKey,
+      amm: this.amm,
+    }).signers([admin]).rpc();
+  }
+
+  async set_flash_loan_fee(admin: Keypair, fee_bps: number): Promise<string> {
+    return await this.swapProgram.methods.setFlashLoanFee(fee_bps).accountsPartial({
+      admin: admin.publicKey,
+      amm: this.amm,
+      poolAccount: this.pool_pda,
+    }).signers([admin]).rpc();
+  }
+
+  async set_circuit_breaker(admin: Keypair, breaker_bps: number, twap_window: BN, pause_on_trigger: boolean): Promise<string> {
+    return await this.swapProgram.methods.setCircuitBreaker(breaker_bps, twap_window, pause_on_trigger).accountsPartial({
+      admin: admin.publicKey,
+      amm: this.amm,
+      poolAccount: this.pool_pda,
+    }).signers([admin]).rpc();
+  }
+
+  async set_permissioned(admin: Keypair, permissioned: boolean): Promise<string> {
+    return await this.swapProgram.methods.setPermissioned(permissioned).accountsPartial({
+      admin: admin.publicKey,
+      amm: this.amm,
+      poolAccount: this.pool_pda,
+    }).signers([admin]).rpc();
+  }
+
+  get_allowlist_pda(user: PublicKey): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync([this.pool_pda.toBuffer(), user.toBuffer(), Buffer.from("ALLOWLIST")], this.swapProgram.programId);
+  }
+
+  // the user's allowlist entry, null while there is none
+  get_allowlist(user: PublicKey): PublicKey | null {
+    const [allowlist] = this.get_allowlist_pda(user);
+    return this.client.getAccount(allowlist) ? allowlist : null;
+  }
+
+  async add_to_allowlist(admin: Keypair, user: PublicKey): Promise<string> {
+    return await this.swapProgram.methods.addToAllowlist(user).accountsPartial({
+      admin: admin.publicKey,
+      amm: this.amm,
+      poolAccount: this.pool_pda,
+      allowlist: this.get_allowlist_pda(user)[0],
+    }).signers([admin]).rpc();
+  }
+
+  async remove_from_allowlist(admin: Keypair, user: PublicKey): Promise<string> {
+    return await this.swapProgram.methods.removeFromAllowlist().accountsPartial({
+      admin: admin.publicKey,
+      amm: this.amm,
+      poolAccount: this.pool_pda,
+      allowlist: this.get_allowlist_pda(user)[0],
+    }).signers([admin]).rpc();
+  }
+
+  async set_withdraw_cooldown(admin: Keypair, cooldown: BN): Promise<string> {
+    return await this.swapProgram.methods.setWithdrawCooldown(cooldown).accountsPartial({
+      admin: admin.publicKey,
+      amm: this.amm,
+      poolAccount: this.pool_pda,
+    }).signers([admin]).rpc();
+  }
+
+  get_withdraw_request_pda(owner: PublicKey): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync([this.pool_pda.toBuffer(), owner.toBuffer(), Buffer.from("WITHDRAW")], this.swapProgram.programId);
+  }
+
+  async request_withdraw(owner: Keypair, liquidity: BN): Promise<string> {
+    const [withdraw_request] = this.get_withdraw_request_pda(owner.publicKey);
+    return await this.swapProgram.methods.requestWithdraw(liquidity).accountsPartial({
+      owner: owner.publicKey,
+      poolAccount: this.pool_pda,
+      mintLiquidity: this.mint_lp_pda,
+      withdrawRequest: withdraw_request,
+      withdrawEscrow: getAssociatedTokenAddressSync(this.mint_lp_pda, withdraw_request, true),
+      ownerAccountLiquidity: this.get_depositor_lp_account(owner),
+    }).signers([owner]).rpc();
+  }
+
+  async cancel_withdraw(owner: Keypair): Promise<string> {
+    const [withdraw_request] = this.get_withdraw_request_pda(owner.publicKey);
+    return await this.swapProgram.methods.cancelWithdraw().accountsPartial({
+      owner: owner.publicKey,
+      withdrawRequest: withdraw_request,
+      withdrawEscrow: getAssociatedTokenAddressSync(this.mint_lp_pda, withdraw_request, true),
+      ownerAccountLiquidity: this.get_depositor_lp_account(owner),
+    }).signers([owner]).rpc();
+  }
+
+  async set_outflow_limit(admin: Keypair, limit_bps: number, window: BN): Promise<string> {
+    return await this.swapProgram.methods.setOutflowLimit(limit_bps, window).accountsPartial({
+      admin: admin.publicKey,
+      amm: this.amm,
+      poolAccount: this.pool_pda,
+    }).signers([admin]).rpc();
+  }
+
+  async set_dynamic_fee(admin: Keypair, enabled: boolean, base_fee_bps: number, max_fee_bps: number, variable_fee_control: number, decay_period: BN): Promise<string> {
+    return await this.swapProgram.methods.setDynamicFee(enabled, base_fee_bps, max_fee_bps, variable_fee_control, decay_period).accountsPartial({
+      admin: admin.publicKey,
+      amm: this.amm,
+      poolAccount: this.pool_pda,
+    }).signers([admin]).rpc();
+  }
+
+  async swap_batch(depositor: Keypair, legs: { amount: BN, minOutput: BN, outputB: boolean }[]): Promise<string> {
+    return await this.swapProgram.methods.swapBatch(legs).accountsPartial({
+      poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
+      amm: this.amm,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      depositor: depositor.publicKey,
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      depositorAccountA: getAssociatedTokenAddressSync(this.token0_mint.publicKey, depositor.publicKey, false, this.token0_program),
+      depositorAccountB: getAssociatedTokenAddressSync(this.token1_mint.publicKey, depositor.publicKey, false, this.token1_program),
+      oracle: this.oracle,
+      allowlist: this.get_allowlist(depositor.publicKey),
+      tokenProgramA: this.token0_program,
+      tokenProgramB: this.token1_program,
+    }).signers([depositor]).rpc();
+  }
+
+  async add_liquidity_single(depositor: Keypair, input_a: boolean, amount_in: BN, min_lp_out: BN): Promise<string> {
+    const input_mint = input_a ? this.token0_mint.publicKey : this.token1_mint.publicKey;
+    return await this.swapProgram.methods.addLiquiditySingle(input_mint, amount_in, min_lp_out).accountsPartial({
+      poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
+      depositor: depositor.publicKey,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      mintLiquidity: this.mint_lp_pda,
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      depositorAccountLiquidity: this.get_depositor_lp_account(depositor),
+      depositorAccountIn: getAssociatedTokenAddressSync(input_mint, depositor.publicKey),
+      oracle: this.oracle,
+    }).signers([depositor]).rpc();
+  }
+
+  async remove_liquidity_one_token(depositor: Keypair, lp_amount: BN, output_a: boolean, min_out: BN): Promise<string> {
+    const output_mint = output_a ? this.token0_mint.publicKey : this.token1_mint.publicKey;
+    return await this.swapProgram.methods.removeLiquidityOneToken(lp_amount, output_mint, min_out).accountsPartial({
+      poolAccount: this.pool_pda,
+      poolStats: this.pool_stats_pda,
+      depositor: depositor.publicKey,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      mintLiquidity: this.mint_lp_pda,
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      depositorAccountLiquidity: this.get_depositor_lp_account(depositor),
+      depositorAccountOut: getAssociatedTokenAddressSync(output_mint, depositor.publicKey),
+      oracle: this.oracle,
+    }).signers([depositor]).rpc();
+  }
+
+  async flash_borrow_ix(borrower: Keypair, amount: BN, borrow_a: boolean, receiver_account: PublicKey): Promise<TransactionInstruction> {
+    return await this.swapProgram.methods.flashBorrow(amount, borrow_a).accountsPartial({
+      poolAccount: this.pool_pda,
+      borrower: borrower.publicKey,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      receiverAccount: receiver_account,
+      instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+      allowlist: this.get_allowlist(borrower.publicKey),
+      tokenProgram: TOKEN_PROGRAM_ID,
+    }).instruction();
+  }
+
+  async flash_repay_ix(repayer: Keypair, repayer_account: PublicKey): Promise<TransactionInstruction> {
+    return await this.swapProgram.methods.flashRepay().accountsPartial({
+      poolAccount: this.pool_pda,
+      repayer: repayer.publicKey,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      repayerAccount: repayer_account,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    }).instruction();
+  }
+
+  async sync_ix(): Promise<TransactionInstruction> {
+    return await this.swapProgram.methods.sync().accountsPartial({
+      poolAccount: this.pool_pda,
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      tokenProgramA: this.token0_program,
+      tokenProgramB: this.token1_program,
+    }).instruction();
+  }
+
+  async skim_ix(recipient_account_a: PublicKey, recipient_account_b: PublicKey): Promise<TransactionInstruction> {
+    return await this.swapProgram.methods.skim().accountsPartial({
+      poolAccount: this.pool_pda,
+      mintA: this.token0_mint.publicKey,
+      mintB: this.token1_mint.publicKey,
+      poolAccountA: this.pool_token0_account,
+      poolAccountB: this.pool_token1_account,
+      recipientAccountA: recipient_account_a,
+      recipientAccountB: recipient_account_b,
+      tokenProgramA: this.token0_program,
+      tokenProgramB: this.token1_program,
+    }).instruction();
+  }
+
+  async set_oracle(admin: Keypair, oracle: PublicKey, max_staleness: BN, max_conf_bps: number, max_deviation_bps: number): Promise<string> {
+    this.oracle = oracle;
+    const transactionSignature = await this.swapProgram.methods.setOracle(oracle, max_staleness, max_conf_bps, max_deviation_bps).accountsPartial({
+      admin: admin.publicKey,
+      amm: this.amm,
+      poolAccount: this.pool_pda,
+    }).signers([admin]).rpc();
+    return transactionSignature;
+  }
+
+  // writes a Pyth v2 price account fixture, only the fields the program reads are filled
+  write_pyth_price(oracle: PublicKey, price: bigint, conf: bigint, expo: number, publish_time: bigint): void {
+    const data = Buffer.alloc(3312);
+    data.writeUInt32LE(0xa1b2c3d4, 0);
+    data.writeUInt32LE(2, 4);
+    data.writeUInt32LE(3, 8);
+    data.writeInt32LE(expo, 20);
+    data.writeBigInt64LE(publish_time, 96);
+    data.writeBigInt64LE(price, 208);
+    data.writeBigUInt64LE(conf, 216);
+    data.writeUInt32LE(1, 224);
+    this.client.setAccount(oracle, {
+      lamports: LAMPORTS_PER_SOL,
+      data,
+      owner: SystemProgram.programId,
+      executable: false,
+    });
   }
 
   async mint_token_to_user(user: Keypair, token: PublicKey ,amount: bigint): Promise<string> {
@@ -286,7 +872,7 @@ export class TestBase {
         token,
         user.publicKey,
         false, // allowOwnerOffCurve
-        TOKEN_PROGRAM_ID,
+        this.token_program(token),
         ASSOCIATED_TOKEN_PROGRAM_ID
       );
 
@@ -296,7 +882,7 @@ export class TestBase {
       this.payer.publicKey, // authority of mint account
       amount, // amount
       [], // multiSigners
-      TOKEN_PROGRAM_ID // programId
+      this.token_program(token) // programId
     );
 
     const mintTransaction = new Transaction().add(mintToInstruction);
@@ -319,6 +905,10 @@ export class TestBase {
       Buffer.from("POOL")], this.swapProgram.programId);
   }
 
+  get_pool_stats_pda(): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync([this.pool_pda.toBuffer(), Buffer.from("STATS")], this.swapProgram.programId);
+  }
+
   get_amm_pda(admin: PublicKey): [PublicKey, number] {
     return PublicKey.findProgramAddressSync([admin.toBuffer()], this.swapProgram.programId);
   }
@@ -341,7 +931,7 @@ export class TestBase {
         token_mint,
         user.publicKey,
         false, // allowOwnerOffCurve
-        TOKEN_PROGRAM_ID,
+        this.token_program(token_mint),
         ASSOCIATED_TOKEN_PROGRAM_ID
       );
 
@@ -351,7 +941,7 @@ export class TestBase {
       associatedTokenAccount, // associated token account address
       user.publicKey, // owner
       token_mint, // mint
-      TOKEN_PROGRAM_ID,
+      this.token_program(token_mint),
       ASSOCIATED_TOKEN_PROGRAM_ID
     );
   
@@ -408,12 +998,48 @@ export class TestBase {
 
   async getMintInfo(mintPublicKey: PublicKey): Promise<any> {
     const info = await this.provider.client.getAccount(mintPublicKey);
-    return unpackMint(mintPublicKey, info as any, TOKEN_PROGRAM_ID);
+    return unpackMint(mintPublicKey, info as any, info.owner);
   }
 
   async getTAInfo(tokenAcount: PublicKey):Promise<any> {
     const info = await this.provider.client.getAccount(tokenAcount);
-    return unpackAccount(tokenAcount, info as any, TOKEN_PROGRAM_ID)
+    return unpackAccount(tokenAcount, info as any, info.owner)
+  }
+
+  token_program(mint: PublicKey): PublicKey {
+    if (mint.equals(this.token0_mint.publicKey)) return this.token0_program;
+    if (mint.equals(this.token1_mint.publicKey)) return this.token1_program;
+    return TOKEN_PROGRAM_ID;
+  }
+
+  // Token-2022 mint charging fee_bps on every transfer, capped at max_fee
+  async createTransferFeeMint(mintKeypair: Keypair, fee_bps: number, max_fee: bigint, decimals: number = 9): Promise<string> {
+    const space = getMintLen([ExtensionType.TransferFeeConfig]);
+    const transaction = new Transaction().add(
+      SystemProgram.createAccount({
+        fromPubkey: this.payer.publicKey,
+        newAccountPubkey: mintKeypair.publicKey,
+        space,
+        lamports: await this.provider.connection.getMinimumBalanceForRentExemption(space),
+        programId: TOKEN_2022_PROGRAM_ID
+      }),
+      createInitializeTransferFeeConfigInstruction(
+        mintKeypair.publicKey,
+        this.payer.publicKey,
+        this.payer.publicKey,
+        fee_bps,
+        max_fee,
+        TOKEN_2022_PROGRAM_ID
+      ),
+      createInitializeMintInstruction(
+        mintKeypair.publicKey,
+        decimals,
+        this.payer.publicKey,
+        this.payer.publicKey,
+        TOKEN_2022_PROGRAM_ID
+      )
+    );
+    return await this.provider.send(transaction, [this.payer, mintKeypair]);
   }
 
   // Utility method to log mint creation details
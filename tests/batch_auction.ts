@@ -0,0 +1,80 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { expect } from "chai";
+
+describe("batch auction", () => {
+  let testBase: TestBase;
+  const balance = async (account) => BigInt((await testBase.getTAInfo(account)).amount);
+  const current_slot = (): bigint => BigInt(testBase.client.getClock().slot);
+  const expect_error = async (promise: Promise<any>, error: string) => {
+    try {
+      await promise;
+      expect.fail(`should fail with ${error}`);
+    } catch (e) {
+      expect(String(e)).to.contain(error);
+    }
+  };
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(1000000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(1000000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(100000), new BN(100000), new BN(1000), new BN(1000));
+    await testBase.set_batch_auction(testBase.payer, true);
+  });
+
+  it("rejects instant swaps", async () => {
+    await expect_error(testBase.swap(testBase.user1, new BN(1000), new BN(0), true), "BatchAuctionActive");
+  });
+
+  it("settles opposing intents at one price", async () => {
+    const slot = new BN(current_slot().toString());
+    await testBase.queue_swap(testBase.user0, slot, new BN(5000), true);
+    await testBase.queue_swap(testBase.user1, slot, new BN(2000), false);
+    await expect_error(testBase.settle_batch(testBase.payer, slot), "BatchNotClosed");
+
+    testBase.warp_to_slot(current_slot() + BigInt(1));
+    const pool_before = await testBase.get_pool();
+    await testBase.settle_batch(testBase.payer, slot);
+    const pool_after = await testBase.get_pool();
+    // only the 3000 a imbalance, less what the matched side absorbs, reaches the pool
+    const pool_in = pool_after.reserveA.sub(pool_before.reserveA).toNumber();
+    expect(pool_in).to.be.within(1, 3000);
+    expect(pool_after.reserveB.lt(pool_before.reserveB)).to.be.true;
+
+    const user0_before = await balance(testBase.user0_token1_account);
+    const user1_before = await balance(testBase.user1_token0_account);
+    await testBase.claim_batch(testBase.user0, slot, true);
+    await testBase.claim_batch(testBase.user1, slot, false);
+    const user0_out = Number((await balance(testBase.user0_token1_account)) - user0_before);
+    const user1_out = Number((await balance(testBase.user1_token0_account)) - user1_before);
+    // both sides clear at reciprocal prices
+    expect((user0_out / 5000) * (user1_out / 2000)).to.be.within(0.99, 1.01);
+    await expect_error(testBase.settle_batch(testBase.payer, slot), "BatchSettled");
+  });
+
+  it("refunds an intent once an unsettled batch expired", async () => {
+    const slot = new BN(current_slot().toString());
+    const before = await balance(testBase.user1_token1_account);
+    await testBase.queue_swap(testBase.user1, slot, new BN(1000), false);
+    testBase.warp_to_slot(current_slot() + BigInt(1));
+    await expect_error(testBase.refund_intent(testBase.user1, slot, false), "BatchNotExpired");
+
+    testBase.warp_to_slot(current_slot() + BigInt(150));
+    await testBase.refund_intent(testBase.user1, slot, false);
+    expect(await balance(testBase.user1_token1_account)).to.equal(before);
+    const [batch] = testBase.get_batch_pda(slot);
+    const batch_account = await testBase.swapProgram.account.batchAuction.fetch(batch);
+    expect(batch_account.totalInB.toNumber()).to.equal(0);
+  });
+});
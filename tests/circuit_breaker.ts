@@ -0,0 +1,101 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { randomBytes } from "crypto";
+import { expect } from "chai";
+
+describe("circuit breaker", () => {
+  let testBase: TestBase;
+  const balance = async (account) => BigInt((await testBase.getTAInfo(account)).amount);
+  const current_slot = (): bigint => BigInt(testBase.client.getClock().slot);
+  const expect_error = async (promise: Promise<any>, error: string) => {
+    try {
+      await promise;
+      expect.fail(`should fail with ${error}`);
+    } catch (e) {
+      expect(String(e)).to.contain(error);
+    }
+  };
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(1000000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(1000000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(100000), new BN(100000), new BN(1000), new BN(1000));
+  });
+
+  it("pauses the pool on a zap that moves the price too far", async () => {
+    await testBase.set_circuit_breaker(testBase.payer, 500, new BN(3600), true);
+    const before = await balance(testBase.user1_token0_account);
+    await testBase.add_liquidity_single(testBase.user1, true, new BN(50000), new BN(0));
+    // the trade is skipped, only the pause is kept
+    expect(await balance(testBase.user1_token0_account)).to.equal(before);
+    expect(await balance(testBase.get_depositor_lp_account(testBase.user1))).to.equal(BigInt(0));
+    expect((await testBase.get_pool()).paused).to.be.true;
+
+    await expect_error(testBase.add_liquidity_single(testBase.user1, true, new BN(100), new BN(0)), "PoolPaused");
+    await expect_error(testBase.remove_liquidity_one_token(testBase.user0, new BN(100), true, new BN(0)), "PoolPaused");
+  });
+
+  it("rejects a one sided withdrawal past the breaker", async () => {
+    await testBase.set_circuit_breaker(testBase.payer, 500, new BN(3600), false);
+    const lp = await balance(testBase.get_depositor_lp_account(testBase.user0));
+    await expect_error(testBase.remove_liquidity_one_token(testBase.user0, new BN((lp / BigInt(2)).toString()), true, new BN(0)), "CircuitBreakerTriggered");
+    expect((await testBase.get_pool()).paused).to.be.false;
+  });
+
+  it("leaves the volatility untouched when a batch pauses the pool", async () => {
+    await testBase.set_dynamic_fee(testBase.payer, true, 30, 100, 1000, new BN(600));
+    await testBase.set_circuit_breaker(testBase.payer, 500, new BN(3600), true);
+    const legs = [
+      { amount: new BN(20000), minOutput: new BN(0), outputB: true },
+      { amount: new BN(20000), minOutput: new BN(0), outputB: true },
+    ];
+    await testBase.swap_batch(testBase.user1, legs);
+    const pool = await testBase.get_pool();
+    expect(pool.paused).to.be.true;
+    expect(pool.volatilityAccumulator.toNumber()).to.equal(0);
+    expect(pool.reserveA.toNumber()).to.equal(100000);
+  });
+
+  it("rejects a fill past the breaker and keeps the order cancellable", async () => {
+    await testBase.set_circuit_breaker(testBase.payer, 500, new BN(3600), true);
+    const before = await balance(testBase.user1_token0_account);
+    await testBase.place_order(testBase.user1, new BN(1), true, new BN(20000), new BN(0), new BN(10));
+    await expect_error(testBase.fill_order(testBase.user0, testBase.user1.publicKey, new BN(1), testBase.user0_token0_account), "CircuitBreakerTriggered");
+    expect((await testBase.get_pool()).paused).to.be.false;
+
+    await testBase.cancel_order(testBase.user1, new BN(1));
+    expect(await balance(testBase.user1_token0_account)).to.equal(before);
+    const [order] = testBase.get_order_pda(testBase.user1.publicKey, new BN(1));
+    expect(testBase.client.getAccount(order)).to.be.null;
+  });
+
+  it("rejects a reveal past the breaker and keeps the commit refundable", async () => {
+    const salt = randomBytes(32);
+    const min_output = new BN(0);
+    const before = await balance(testBase.user1_token0_account);
+    await testBase.commit_swap(testBase.user1, new BN(1), new BN(20000), true, testBase.get_commitment(min_output, salt));
+    const committed = current_slot();
+
+    // COMMIT_MIN_SLOTS / COMMIT_EXPIRY_SLOTS in utils.rs
+    testBase.warp_to_slot(committed + BigInt(2));
+    await expect_error(testBase.reveal_swap(testBase.user1, new BN(1), true, min_output, salt), "CircuitBreakerTriggered");
+    expect((await testBase.get_pool()).paused).to.be.false;
+
+    testBase.warp_to_slot(committed + BigInt(151));
+    await testBase.refund_commit(testBase.user1, new BN(1), true);
+    expect(await balance(testBase.user1_token0_account)).to.equal(before);
+    const [commit] = testBase.get_commit_pda(testBase.user1.publicKey, new BN(1));
+    expect(testBase.client.getAccount(commit)).to.be.null;
+  });
+});
@@ -0,0 +1,81 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { randomBytes } from "crypto";
+import { expect } from "chai";
+
+describe("commit reveal", () => {
+  let testBase: TestBase;
+  // COMMIT_MIN_SLOTS / COMMIT_EXPIRY_SLOTS in utils.rs
+  const min_slots = BigInt(2);
+  const expiry_slots = BigInt(150);
+  const balance = async (account) => BigInt((await testBase.getTAInfo(account)).amount);
+  const current_slot = (): bigint => BigInt(testBase.client.getClock().slot);
+  const expect_error = async (promise: Promise<any>, error: string) => {
+    try {
+      await promise;
+      expect.fail(`should fail with ${error}`);
+    } catch (e) {
+      expect(String(e)).to.contain(error);
+    }
+  };
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(1000000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(1000000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(100000), new BN(100000), new BN(1000), new BN(1000));
+  });
+
+  it("reveals between the min and expiry slots", async () => {
+    const salt = randomBytes(32);
+    const min_output = new BN(900);
+    await testBase.commit_swap(testBase.user1, new BN(1), new BN(1000), true, testBase.get_commitment(min_output, salt));
+    const committed = current_slot();
+    await expect_error(testBase.reveal_swap(testBase.user1, new BN(1), true, min_output, salt), "CommitTooEarly");
+
+    testBase.warp_to_slot(committed + min_slots);
+    const before = await balance(testBase.user1_token1_account);
+    await testBase.reveal_swap(testBase.user1, new BN(1), true, min_output, salt);
+    expect(Number((await balance(testBase.user1_token1_account)) - before)).to.be.within(900, 1000);
+    const [commit] = testBase.get_commit_pda(testBase.user1.publicKey, new BN(1));
+    expect(testBase.client.getAccount(commit)).to.be.null;
+  });
+
+  it("rejects a reveal that does not match the commitment", async () => {
+    const salt = randomBytes(32);
+    await testBase.commit_swap(testBase.user1, new BN(2), new BN(1000), false, testBase.get_commitment(new BN(900), salt));
+    testBase.warp_to_slot(current_slot() + min_slots);
+    // a lower min_output than committed
+    await expect_error(testBase.reveal_swap(testBase.user1, new BN(2), false, new BN(1), salt), "InvalidCommitment");
+    await expect_error(testBase.reveal_swap(testBase.user1, new BN(2), false, new BN(900), randomBytes(32)), "InvalidCommitment");
+  });
+
+  it("refunds only after the reveal window", async () => {
+    const salt = randomBytes(32);
+    const min_output = new BN(900);
+    const before = await balance(testBase.user1_token0_account);
+    await testBase.commit_swap(testBase.user1, new BN(3), new BN(1000), true, testBase.get_commitment(min_output, salt));
+    const committed = current_slot();
+    expect(before - (await balance(testBase.user1_token0_account))).to.equal(BigInt(1000));
+
+    testBase.warp_to_slot(committed + expiry_slots);
+    await expect_error(testBase.refund_commit(testBase.user1, new BN(3), true), "CommitNotExpired");
+
+    testBase.warp_to_slot(committed + expiry_slots + BigInt(1));
+    await expect_error(testBase.reveal_swap(testBase.user1, new BN(3), true, min_output, salt), "CommitExpired");
+    await testBase.refund_commit(testBase.user1, new BN(3), true);
+    expect(await balance(testBase.user1_token0_account)).to.equal(before);
+    const [commit] = testBase.get_commit_pda(testBase.user1.publicKey, new BN(3));
+    expect(testBase.client.getAccount(commit)).to.be.null;
+  });
+});
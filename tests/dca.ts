@@ -0,0 +1,65 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { expect } from "chai";
+
+describe("dca", () => {
+  let testBase: TestBase;
+  const balance = async (account) => BigInt((await testBase.getTAInfo(account)).amount);
+  const execute = () => testBase.execute_dca_slice(testBase.user0, testBase.user1.publicKey, new BN(1), testBase.user0_token0_account);
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(10000), new BN(10000), new BN(1000), new BN(1000));
+
+    // 3 slices of 500 a every minute, 5 a of every slice go to the keeper
+    await testBase.create_dca(testBase.user1, new BN(1), true, new BN(1500), new BN(500), new BN(60), new BN(0), new BN(5));
+  });
+
+  it("executes the first slice right away", async () => {
+    const owner_before = await balance(testBase.user1_token1_account);
+    const keeper_before = await balance(testBase.user0_token0_account);
+    await execute();
+    expect(Number((await balance(testBase.user1_token1_account)) - owner_before)).to.be.within(460, 495);
+    expect((await balance(testBase.user0_token0_account)) - keeper_before).to.equal(BigInt(5));
+  });
+
+  it("rejects a slice before the interval elapsed", async () => {
+    testBase.warp(30);
+    try {
+      await execute();
+      expect.fail("slice should fail");
+    } catch (e) {
+      expect(String(e)).to.contain("DcaIntervalNotElapsed");
+    }
+  });
+
+  it("executes the next slice once the interval elapsed", async () => {
+    testBase.warp(30);
+    const owner_before = await balance(testBase.user1_token1_account);
+    await execute();
+    expect((await balance(testBase.user1_token1_account)) > owner_before).to.be.true;
+  });
+
+  it("rejects a slice below min_price", async () => {
+    // at least 1.1 b per a can not be met near 1:1
+    await testBase.create_dca(testBase.user1, new BN(2), true, new BN(1000), new BN(500), new BN(60), new BN(1_100_000_000), new BN(0));
+    try {
+      await testBase.execute_dca_slice(testBase.user0, testBase.user1.publicKey, new BN(2), testBase.user0_token0_account);
+      expect.fail("slice should fail");
+    } catch (e) {
+      expect(String(e)).to.contain("OrderPriceNotReached");
+    }
+  });
+});
@@ -0,0 +1,85 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { TOKEN_2022_PROGRAM_ID } from "@solana/spl-token";
+import { expect } from "chai";
+
+describe("fee on transfer", () => {
+  let testBase: TestBase;
+  const balance = async (account) => BigInt((await testBase.getTAInfo(account)).amount);
+  const expect_reserves_match_vaults = async () => {
+    const pool = await testBase.get_pool();
+    expect(BigInt(pool.reserveA.toString())).to.equal(await balance(testBase.pool_token0_account));
+    expect(BigInt(pool.reserveB.toString())).to.equal(await balance(testBase.pool_token1_account));
+    return pool;
+  };
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    // token0 keeps 1% of every transfer
+    testBase.token0_program = TOKEN_2022_PROGRAM_ID;
+    await testBase.createTransferFeeMint(testBase.token0_mint, 100, BigInt(1000000));
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+  });
+
+  it("rejects a transfer fee mint that is not flagged", async () => {
+    try {
+      await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+      expect.fail("create_pool should fail");
+    } catch (e) {
+      expect(String(e)).to.contain("FeeOnTransferNotFlagged");
+    }
+  });
+
+  it("credits the amount that reached the vault on deposit", async () => {
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey, true, false);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(10000), new BN(10000), new BN(1000), new BN(1000));
+
+    const pool = await expect_reserves_match_vaults();
+    expect(pool.feeOnTransferA).to.be.true;
+    expect(pool.reserveA.toNumber()).to.equal(9900);
+    expect(pool.reserveB.toNumber()).to.equal(10000);
+  });
+
+  it("prices a swap on the received input", async () => {
+    const before_b = await balance(testBase.user1_token1_account);
+    await testBase.swap(testBase.user1, new BN(1000), new BN(0), true);
+    const output = Number((await balance(testBase.user1_token1_account)) - before_b);
+
+    // 990 reach the vault, pricing the full 1000 would pay out about 914
+    expect(output).to.be.within(900, 910);
+    const pool = await expect_reserves_match_vaults();
+    expect(pool.reserveA.toNumber()).to.equal(9900 + 990);
+    expect(pool.reserveB.toNumber()).to.equal(10000 - output);
+  });
+
+  it("keeps the reserves on the vault balances when the flagged mint is the output", async () => {
+    const before_a = await balance(testBase.user1_token0_account);
+    await testBase.swap(testBase.user1, new BN(1000), new BN(0), false);
+    expect(await balance(testBase.user1_token0_account)).to.be.greaterThan(before_a);
+    await expect_reserves_match_vaults();
+  });
+
+  it("withdraws against the vault balances", async () => {
+    const lp = await balance(testBase.get_depositor_lp_account(testBase.user0));
+    await testBase.remove_liquidity(testBase.user0, new BN(0), new BN(0), new BN((lp / BigInt(2)).toString()));
+    await expect_reserves_match_vaults();
+  });
+
+  it("rejects netted batches on a flagged pool", async () => {
+    try {
+      await testBase.swap_batch(testBase.user1, [{ amount: new BN(100), minOutput: new BN(0), outputB: true }]);
+      expect.fail("swap_batch should fail");
+    } catch (e) {
+      expect(String(e)).to.contain("FeeOnTransferUnsupported");
+    }
+  });
+});
@@ -0,0 +1,77 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { expect } from "chai";
+
+describe("flash loan", () => {
+  let testBase: TestBase;
+
+  const expect_error = async (promise: Promise<any>, error: string) => {
+    try {
+      await promise;
+      expect.fail(`should fail with ${error}`);
+    } catch (e) {
+      expect(String(e)).to.contain(error);
+    }
+  };
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(10000), new BN(10000), new BN(1000), new BN(1000));
+    await testBase.set_flash_loan_fee(testBase.payer, 30);
+  });
+
+  it("rejects a borrow without a later repay", async () => {
+    const borrow = await testBase.flash_borrow_ix(testBase.user1, new BN(1000), true, testBase.user1_token0_account);
+    await expect_error(testBase.send([borrow], [testBase.user1]), "FlashLoanNoRepay");
+  });
+
+  it("rejects a second borrow before the repay", async () => {
+    const borrow = await testBase.flash_borrow_ix(testBase.user1, new BN(1000), true, testBase.user1_token0_account);
+    const second = await testBase.flash_borrow_ix(testBase.user1, new BN(500), false, testBase.user1_token1_account);
+    const repay = await testBase.flash_repay_ix(testBase.user1, testBase.user1_token0_account);
+    await expect_error(testBase.send([borrow, second, repay], [testBase.user1]), "FlashLoanActive");
+  });
+
+  it("rejects sync, skim and swap while a loan is open", async () => {
+    const borrow = await testBase.flash_borrow_ix(testBase.user1, new BN(1000), true, testBase.user1_token0_account);
+    const repay = await testBase.flash_repay_ix(testBase.user1, testBase.user1_token0_account);
+    const inner = [
+      await testBase.sync_ix(),
+      await testBase.skim_ix(testBase.user1_token0_account, testBase.user1_token1_account),
+      await testBase.swap_ix(testBase.user1, new BN(100), new BN(0), true),
+    ];
+    for (const instruction of inner) {
+      await expect_error(testBase.send([borrow, instruction, repay], [testBase.user1]), "FlashLoanActive");
+    }
+  });
+
+  it("credits the fee to the reserve", async () => {
+    const pool_before = await testBase.get_pool();
+    const vault_before = BigInt((await testBase.getTAInfo(testBase.pool_token0_account)).amount);
+    const user_before = BigInt((await testBase.getTAInfo(testBase.user1_token0_account)).amount);
+
+    const borrow = await testBase.flash_borrow_ix(testBase.user1, new BN(1000), true, testBase.user1_token0_account);
+    const repay = await testBase.flash_repay_ix(testBase.user1, testBase.user1_token0_account);
+    await testBase.send([borrow, repay], [testBase.user1]);
+
+    // 1000 * 30 / 10000 = 3
+    const pool_after = await testBase.get_pool();
+    expect(pool_after.reserveA.sub(pool_before.reserveA).toNumber()).to.equal(3);
+    expect(pool_after.reserveB.eq(pool_before.reserveB)).to.be.true;
+    expect(pool_after.flashLoanActive).to.be.false;
+    expect(BigInt((await testBase.getTAInfo(testBase.pool_token0_account)).amount) - vault_before).to.equal(BigInt(3));
+    expect(user_before - BigInt((await testBase.getTAInfo(testBase.user1_token0_account)).amount)).to.equal(BigInt(3));
+  });
+});
@@ -0,0 +1,51 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { TOKEN_PROGRAM_ID, createTransferInstruction } from "@solana/spl-token";
+import { expect } from "chai";
+
+describe("flash swap", () => {
+  let testBase: TestBase;
+
+  // the token program doubles as the receiver, its callback pays token0 back from user1
+  const flash_swap = async (amount_out: number, amount_in: number) => {
+    const callback = createTransferInstruction(testBase.user1_token0_account, testBase.pool_token0_account, testBase.user1.publicKey, amount_in, [], TOKEN_PROGRAM_ID);
+    const instruction = await testBase.flash_swap_ix(testBase.user1, new BN(amount_out), true, callback);
+    return await testBase.send([instruction], [testBase.user1]);
+  };
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(10000), new BN(10000), new BN(1000), new BN(1000));
+  });
+
+  it("rejects a callback that underpays", async () => {
+    // 1000 out of 10000 needs about 1115 in at 30 bps
+    try {
+      await flash_swap(1000, 1000);
+      expect.fail("flash swap should fail");
+    } catch (e) {
+      expect(String(e)).to.contain("InvariantViolated");
+    }
+  });
+
+  it("settles when the callback pays the fee adjusted input", async () => {
+    const before = BigInt((await testBase.getTAInfo(testBase.user1_token1_account)).amount);
+    await flash_swap(1000, 1120);
+    expect(BigInt((await testBase.getTAInfo(testBase.user1_token1_account)).amount) - before).to.equal(BigInt(1000));
+    const pool = await testBase.get_pool();
+    expect(pool.reserveA.toNumber()).to.equal(11120);
+    expect(pool.reserveB.toNumber()).to.equal(9000);
+  });
+});
@@ -0,0 +1,70 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { expect } from "chai";
+
+describe("limit order", () => {
+  let testBase: TestBase;
+  const balance = async (account) => BigInt((await testBase.getTAInfo(account)).amount);
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(10000), new BN(10000), new BN(1000), new BN(1000));
+  });
+
+  it("does not fill before the limit price is reached", async () => {
+    // 1000 a currently buys about 906 b
+    await testBase.place_order(testBase.user1, new BN(1), true, new BN(1000), new BN(1000), new BN(10));
+    try {
+      await testBase.fill_order(testBase.user0, testBase.user1.publicKey, new BN(1), testBase.user0_token0_account);
+      expect.fail("fill should fail");
+    } catch (e) {
+      expect(String(e)).to.contain("OrderPriceNotReached");
+    }
+  });
+
+  it("fills at the pool price and pays the tip", async () => {
+    const owner_before = await balance(testBase.user1_token1_account);
+    const filler_before = await balance(testBase.user0_token0_account);
+    await testBase.place_order(testBase.user1, new BN(2), true, new BN(1000), new BN(900), new BN(10));
+    await testBase.fill_order(testBase.user0, testBase.user1.publicKey, new BN(2), testBase.user0_token0_account);
+
+    const output = (await balance(testBase.user1_token1_account)) - owner_before;
+    expect(Number(output)).to.be.within(900, 910);
+    expect((await balance(testBase.user0_token0_account)) - filler_before).to.equal(BigInt(10));
+    const pool = await testBase.get_pool();
+    expect(pool.reserveA.toNumber()).to.equal(11000);
+    expect(pool.reserveB.toNumber()).to.equal(10000 - Number(output));
+    const [order] = testBase.get_order_pda(testBase.user1.publicKey, new BN(2));
+    expect(testBase.client.getAccount(order)).to.be.null;
+
+    const stats = await testBase.swapProgram.account.poolStats.fetch(testBase.pool_stats_pda);
+    expect(stats.swapCount.toNumber()).to.equal(1);
+    expect(stats.volumeA.toNumber()).to.equal(1000);
+    expect(stats.volumeB.toNumber()).to.equal(Number(output));
+    expect(stats.feesA.toNumber()).to.equal(3);
+  });
+
+  it("counts single sided deposits and withdrawals", async () => {
+    await testBase.add_liquidity_single(testBase.user1, true, new BN(500), new BN(0));
+    const lp = await balance(testBase.get_depositor_lp_account(testBase.user1));
+    await testBase.remove_liquidity_one_token(testBase.user1, new BN((lp / BigInt(2)).toString()), false, new BN(0));
+
+    const stats = await testBase.swapProgram.account.poolStats.fetch(testBase.pool_stats_pda);
+    // the seed deposit plus the zap, each inner swap is a trade
+    expect(stats.depositCount.toNumber()).to.equal(2);
+    expect(stats.withdrawCount.toNumber()).to.equal(1);
+    expect(stats.swapCount.toNumber()).to.equal(3);
+  });
+});
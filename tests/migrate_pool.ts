@@ -0,0 +1,88 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { expect } from "chai";
+
+describe("migrate pool", () => {
+  let testBase: TestBase;
+  const balance = async (account) => BigInt((await testBase.getTAInfo(account)).amount);
+  // discriminator + mint_a + mint_b + amm + bump, the Pool layout before any field was appended
+  const legacy_pool_len = 8 + 32 + 32 + 32 + 1;
+  // discriminator + admin + bump, the Amm layout before max_referral_bps
+  const legacy_amm_len = 8 + 32 + 1;
+
+  // rewrites an account as an older program version left it
+  const truncate = (address, len: number) => {
+    const account = testBase.client.getAccount(address);
+    testBase.client.setAccount(address, { ...account, data: account.data.slice(0, len) });
+  };
+  const remove = (address) => {
+    testBase.client.setAccount(address, { lamports: 0, data: new Uint8Array(), owner: testBase.swapProgram.programId, executable: false });
+  };
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(10000), new BN(10000), new BN(1000), new BN(1000));
+
+    // a pool from before reserve tracking, stats and the locked LP account
+    truncate(testBase.pool_pda, legacy_pool_len);
+    truncate(testBase.amm, legacy_amm_len);
+    remove(testBase.pool_stats_pda);
+    remove(testBase.pool_lp_account);
+  });
+
+  it("can not load a pool with the old layout", async () => {
+    try {
+      await testBase.get_pool();
+      expect.fail("old layout should not deserialize");
+    } catch (e) {
+      expect(String(e)).to.not.contain("old layout should not deserialize");
+    }
+  });
+
+  it("grows the pool and amm and seeds the reserves from the vaults", async () => {
+    await testBase.migrate_pool(testBase.payer);
+    const pool = await testBase.get_pool();
+    expect(pool.version).to.equal(2);
+    expect(pool.reserveA.toString()).to.equal((await balance(testBase.pool_token0_account)).toString());
+    expect(pool.reserveB.toString()).to.equal((await balance(testBase.pool_token1_account)).toString());
+    expect(pool.paused).to.be.false;
+    expect(pool.permissioned).to.be.false;
+
+    const amm = await testBase.swapProgram.account.amm.fetch(testBase.amm);
+    expect(amm.maxReferralBps).to.equal(0);
+    const stats = await testBase.swapProgram.account.poolStats.fetch(testBase.pool_stats_pda);
+    expect(stats.pool.equals(testBase.pool_pda)).to.be.true;
+  });
+
+  it("trades on the migrated pool and keeps its state on a second run", async () => {
+    await testBase.swap(testBase.user1, new BN(1000), new BN(0), true);
+    const before = await testBase.get_pool();
+    await testBase.migrate_pool(testBase.payer);
+    const after = await testBase.get_pool();
+    expect(after.reserveA.eq(before.reserveA)).to.be.true;
+    expect(after.reserveB.eq(before.reserveB)).to.be.true;
+    const stats = await testBase.swapProgram.account.poolStats.fetch(testBase.pool_stats_pda);
+    expect(stats.swapCount.toNumber()).to.equal(1);
+  });
+
+  it("only lets the amm admin migrate", async () => {
+    try {
+      await testBase.migrate_pool(testBase.user1);
+      expect.fail("should fail for another signer");
+    } catch (e) {
+      expect(String(e)).to.contain("ConstraintSeeds");
+    }
+  });
+});
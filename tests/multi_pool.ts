@@ -0,0 +1,146 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { getAssociatedTokenAddressSync, createAssociatedTokenAccountInstruction } from "@solana/spl-token";
+import { createHash } from "crypto";
+import { expect } from "chai";
+
+describe("multi pool", () => {
+  let testBase: TestBase;
+  const token2_mint = Keypair.generate();
+  let mints: PublicKey[];
+  let multi_pool: PublicKey;
+  let mint_liquidity: PublicKey;
+  let vaults: PublicKey[];
+
+  const readonly = (keys: PublicKey[]) => keys.map((pubkey) => ({ pubkey, isWritable: false, isSigner: false }));
+  const writable = (keys: PublicKey[]) => keys.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }));
+  const user_accounts = (user: Keypair) => mints.map((mint) => getAssociatedTokenAddressSync(mint, user.publicKey));
+  const lp_account = (user: Keypair) => getAssociatedTokenAddressSync(mint_liquidity, user.publicKey);
+  const balance = async (account: PublicKey) => BigInt((await testBase.getTAInfo(account)).amount);
+
+  const add_liquidity_multi = (depositor: Keypair, amounts: number[], min_lp_out: number) =>
+    testBase.swapProgram.methods.addLiquidityMulti(amounts.map((amount) => new BN(amount)), new BN(min_lp_out)).accountsPartial({
+      multiPool: multi_pool,
+      depositor: depositor.publicKey,
+      mintLiquidity: mint_liquidity,
+      poolAccountLiquidity: getAssociatedTokenAddressSync(mint_liquidity, multi_pool, true),
+      depositorAccountLiquidity: lp_account(depositor),
+    }).remainingAccounts(writable([...vaults, ...user_accounts(depositor)])).signers([depositor]).rpc();
+
+  const swap_multi = (depositor: Keypair, index_in: number, index_out: number, amount: number, min_output: number) =>
+    testBase.swapProgram.methods.swapMulti(index_in, index_out, new BN(amount), new BN(min_output)).accountsPartial({
+      multiPool: multi_pool,
+      depositor: depositor.publicKey,
+      depositorAccountIn: user_accounts(depositor)[index_in],
+      depositorAccountOut: user_accounts(depositor)[index_out],
+    }).remainingAccounts(writable(vaults)).signers([depositor]).rpc();
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.createMint(token2_mint);
+    await testBase.setup_user_token();
+    await testBase.CreateUserATA(testBase.user0, token2_mint.publicKey);
+    await testBase.CreateUserATA(testBase.user1, token2_mint.publicKey);
+    mints = [testBase.token0_mint.publicKey, testBase.token1_mint.publicKey, token2_mint.publicKey];
+    for (const mint of mints) {
+      await testBase.mint_token_to_user(testBase.user0, mint, BigInt(1_000_000));
+      await testBase.mint_token_to_user(testBase.user1, mint, BigInt(1_000_000));
+    }
+    await testBase.create_amm(testBase.payer);
+
+    const mints_hash = createHash("sha256").update(Buffer.concat(mints.map((mint) => mint.toBuffer()))).digest();
+    [multi_pool] = PublicKey.findProgramAddressSync([testBase.payer.publicKey.toBuffer(), mints_hash, Buffer.from("MULTI_POOL")], testBase.swapProgram.programId);
+    [mint_liquidity] = PublicKey.findProgramAddressSync([multi_pool.toBuffer(), Buffer.from("LP_MINT")], testBase.swapProgram.programId);
+    vaults = mints.map((mint) => getAssociatedTokenAddressSync(mint, multi_pool, true));
+
+    await testBase.swapProgram.methods.createMultiPool(Array.from(mints_hash), new BN(100), 4).accountsPartial({
+      admin: testBase.payer.publicKey,
+      amm: testBase.amm,
+      multiPool: multi_pool,
+      mintLiquidity: mint_liquidity,
+    }).remainingAccounts(readonly(mints)).signers([testBase.payer]).rpc();
+    // a third party creating a vault first must not block the pool setup
+    await testBase.send([createAssociatedTokenAccountInstruction(testBase.user1.publicKey, vaults[0], multi_pool, mints[0])], [testBase.user1]);
+    await testBase.swapProgram.methods.createMultiPoolToken().accountsPartial({
+      payer: testBase.payer.publicKey,
+      multiPool: multi_pool,
+      mintLiquidity: mint_liquidity,
+      poolAccountLiquidity: getAssociatedTokenAddressSync(mint_liquidity, multi_pool, true),
+    }).remainingAccounts(mints.flatMap((mint, i) => [...readonly([mint]), ...writable([vaults[i]])])).signers([testBase.payer]).rpc();
+  });
+
+  it("creates every vault for the pool, including one made ahead of it", async () => {
+    for (const [i, vault] of vaults.entries()) {
+      const info = await testBase.getTAInfo(vault);
+      expect(info.mint.equals(mints[i])).to.be.true;
+      expect(info.owner.equals(multi_pool)).to.be.true;
+    }
+  });
+
+  it("mints D minus the locked liquidity on the first deposit", async () => {
+    await add_liquidity_multi(testBase.user0, [100_000, 100_000, 100_000], 0);
+    for (const vault of vaults) {
+      expect(await balance(vault)).to.equal(BigInt(100_000));
+    }
+    // a balanced pool has D = sum of the balances
+    const lp = await balance(lp_account(testBase.user0));
+    expect(Number(lp)).to.be.within(299_000 - 1, 299_000 + 1);
+  });
+
+  it("swaps close to one to one", async () => {
+    const [account_in, account_out] = user_accounts(testBase.user1);
+    const before_in = await balance(account_in);
+    const before_out = await balance(account_out);
+    await swap_multi(testBase.user1, 0, 1, 1_000, 990);
+    expect(before_in - (await balance(account_in))).to.equal(BigInt(1_000));
+    const output = (await balance(account_out)) - before_out;
+    expect(Number(output)).to.be.within(990, 999);
+  });
+
+  it("rejects a swap below min_output", async () => {
+    try {
+      await swap_multi(testBase.user1, 1, 2, 1_000, 1_000);
+      expect.fail("swap should fail");
+    } catch (e) {
+      expect(String(e)).to.contain("InsufficientOutputAmount");
+    }
+  });
+
+  it("takes a proportional deposit from later depositors", async () => {
+    const before = await Promise.all(user_accounts(testBase.user1).map(balance));
+    await add_liquidity_multi(testBase.user1, [10_000, 10_000, 10_000], 1);
+    const after = await Promise.all(user_accounts(testBase.user1).map(balance));
+    expect(await balance(lp_account(testBase.user1)) > BigInt(0)).to.be.true;
+    // the vault that gained from the swap limits the ratio, the others take at most the offered amount
+    before.forEach((amount, i) => expect(Number(amount - after[i])).to.be.within(1, 10_000));
+  });
+
+  it("rejects a deposit below min_lp_out", async () => {
+    try {
+      await add_liquidity_multi(testBase.user1, [10_000, 10_000, 10_000], 1_000_000);
+      expect.fail("deposit should fail");
+    } catch (e) {
+      expect(String(e)).to.contain("InsufficientLiquidityMinted");
+    }
+  });
+
+  it("removes liquidity pro rata", async () => {
+    const lp = await balance(lp_account(testBase.user0));
+    const before = await Promise.all(user_accounts(testBase.user0).map(balance));
+    const remove = lp / BigInt(2);
+    await testBase.swapProgram.methods.removeLiquidityMulti(new BN(remove.toString()), [new BN(40_000), new BN(40_000), new BN(40_000)]).accountsPartial({
+      multiPool: multi_pool,
+      depositor: testBase.user0.publicKey,
+      mintLiquidity: mint_liquidity,
+      depositorAccountLiquidity: lp_account(testBase.user0),
+    }).remainingAccounts(writable([...vaults, ...user_accounts(testBase.user0)])).signers([testBase.user0]).rpc();
+    expect(await balance(lp_account(testBase.user0))).to.equal(lp - remove);
+    const after = await Promise.all(user_accounts(testBase.user0).map(balance));
+    before.forEach((amount, i) => expect(Number(after[i] - amount)).to.be.within(40_000, 60_000));
+  });
+});
@@ -0,0 +1,72 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { Keypair } from "@solana/web3.js";
+import { expect } from "chai";
+
+describe("oracle", () => {
+  let testBase: TestBase;
+  const oracle = Keypair.generate().publicKey;
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(10000), new BN(10000), new BN(1000), new BN(1000));
+
+    // 1.00000000 b per a, confidence 0.1%, deviation at most 5%
+    testBase.write_pyth_price(oracle, BigInt(100_000_000), BigInt(100_000), -8, testBase.client.getClock().unixTimestamp);
+    await testBase.set_oracle(testBase.payer, oracle, new BN(60), 50, 500);
+  });
+
+  it("swaps close to the oracle price", async () => {
+    await testBase.swap(testBase.user1, new BN(100), new BN(90), true);
+  });
+
+  it("rejects swaps far from the oracle price", async () => {
+    testBase.write_pyth_price(oracle, BigInt(200_000_000), BigInt(100_000), -8, testBase.client.getClock().unixTimestamp);
+    try {
+      await testBase.swap(testBase.user1, new BN(100), new BN(0), true);
+      expect.fail("swap should fail");
+    } catch (e) {
+      expect(String(e)).to.contain("OraclePriceDeviation");
+    }
+  });
+
+  it("checks zaps and order fills against the oracle", async () => {
+    testBase.write_pyth_price(oracle, BigInt(200_000_000), BigInt(100_000), -8, testBase.client.getClock().unixTimestamp);
+    await testBase.place_order(testBase.user1, new BN(1), true, new BN(100), new BN(0), new BN(0));
+    const attempts = [
+      () => testBase.add_liquidity_single(testBase.user1, true, new BN(100), new BN(0)),
+      () => testBase.remove_liquidity_one_token(testBase.user0, new BN(100), true, new BN(0)),
+      () => testBase.fill_order(testBase.user0, testBase.user1.publicKey, new BN(1), testBase.user0_token0_account),
+    ];
+    for (const attempt of attempts) {
+      try {
+        await attempt();
+        expect.fail("trade should fail");
+      } catch (e) {
+        expect(String(e)).to.contain("OraclePriceDeviation");
+      }
+    }
+  });
+
+  it("rejects a stale oracle", async () => {
+    testBase.write_pyth_price(oracle, BigInt(100_000_000), BigInt(100_000), -8, testBase.client.getClock().unixTimestamp - BigInt(120));
+    try {
+      await testBase.swap(testBase.user1, new BN(100), new BN(0), true);
+      expect.fail("swap should fail");
+    } catch (e) {
+      expect(String(e)).to.contain("OracleStale");
+    }
+  });
+});
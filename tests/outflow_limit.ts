@@ -0,0 +1,53 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { expect } from "chai";
+
+describe("outflow limit", () => {
+  let testBase: TestBase;
+  const expect_error = async (promise: Promise<any>, error: string) => {
+    try {
+      await promise;
+      expect.fail(`should fail with ${error}`);
+    } catch (e) {
+      expect(String(e)).to.contain(error);
+    }
+  };
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(1000000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(1000000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(100000), new BN(100000), new BN(1000), new BN(1000));
+    // at most 10% of a reserve may leave per hour
+    await testBase.set_outflow_limit(testBase.payer, 1000, new BN(3600));
+  });
+
+  it("rejects outflow beyond the limit inside the window", async () => {
+    // about 5.6k b out of 100k
+    await testBase.swap(testBase.user1, new BN(6000), new BN(0), true);
+    // another 5k b would take the window past 10% of the remaining reserve
+    await expect_error(testBase.swap(testBase.user1, new BN(6000), new BN(0), true), "OutflowLimitExceeded");
+  });
+
+  it("lets the recorded outflow decay over the window", async () => {
+    // half of the first swap's outflow is left
+    testBase.warp(1800);
+    await testBase.swap(testBase.user1, new BN(6000), new BN(0), true);
+    const pool = await testBase.get_pool();
+    expect(pool.outflowB.toNumber()).to.be.within(7000, 9000);
+
+    testBase.warp(3600);
+    await testBase.swap(testBase.user1, new BN(6000), new BN(0), true);
+    expect((await testBase.get_pool()).outflowB.toNumber()).to.be.below(6000);
+  });
+});
@@ -0,0 +1,64 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { getAssociatedTokenAddressSync } from "@solana/spl-token";
+import { expect } from "chai";
+
+describe("swap", () => {
+  let testBase: TestBase;
+  const balance = async (account) => BigInt((await testBase.getTAInfo(account)).amount);
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(1000000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(1000000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(100000), new BN(100000), new BN(1000), new BN(1000));
+  });
+
+  it("rejects a swap that moves the price more than max_price_impact_bps", async () => {
+    // 10000 a into 100000/100000 moves b per a by about 17%
+    try {
+      await testBase.swap(testBase.user1, new BN(10000), new BN(0), true, 1000);
+      expect.fail("swap should fail");
+    } catch (e) {
+      expect(String(e)).to.contain("PriceImpactTooHigh");
+    }
+  });
+
+  it("accepts a swap within max_price_impact_bps", async () => {
+    const before = await balance(testBase.user1_token1_account);
+    await testBase.swap(testBase.user1, new BN(10000), new BN(9000), true, 2000);
+    expect(Number((await balance(testBase.user1_token1_account)) - before)).to.be.within(9000, 9100);
+  });
+
+  it("rejects referral_bps above the amm cap", async () => {
+    const [, referrer_account] = await testBase.CreateUserATA(testBase.payer, testBase.token0_mint.publicKey);
+    try {
+      await testBase.swap(testBase.user1, new BN(10000), new BN(0), true, null, 100, referrer_account);
+      expect.fail("swap should fail");
+    } catch (e) {
+      expect(String(e)).to.contain("InvalidReferralFee");
+    }
+  });
+
+  it("pays the referrer its share of the input fee", async () => {
+    await testBase.set_max_referral_bps(testBase.payer, 5000);
+    const referrer_account = getAssociatedTokenAddressSync(testBase.token0_mint.publicKey, testBase.payer.publicKey);
+    const pool_before = await testBase.get_pool();
+    await testBase.swap(testBase.user1, new BN(10000), new BN(0), true, null, 5000, referrer_account);
+
+    // fee 10000 * 30 bps = 30, half of it goes to the referrer
+    expect(await balance(referrer_account)).to.equal(BigInt(15));
+    const pool_after = await testBase.get_pool();
+    expect(pool_after.reserveA.sub(pool_before.reserveA).toNumber()).to.equal(9985);
+  });
+});
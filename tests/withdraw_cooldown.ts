@@ -0,0 +1,79 @@
+import { TestBase } from "./base";
+import { BN } from "@coral-xyz/anchor";
+import { getAssociatedTokenAddressSync } from "@solana/spl-token";
+import { expect } from "chai";
+
+describe("withdraw cooldown", () => {
+  let testBase: TestBase;
+  const balance = async (account) => BigInt((await testBase.getTAInfo(account)).amount);
+  const expect_error = async (promise: Promise<any>, error: string) => {
+    try {
+      await promise;
+      expect.fail(`should fail with ${error}`);
+    } catch (e) {
+      expect(String(e)).to.contain(error);
+    }
+  };
+
+  before(async () => {
+    testBase = new TestBase();
+    await testBase.initialize();
+
+    await testBase.createToken0Mint();
+    await testBase.createToken1Mint();
+    await testBase.setup_user_token();
+    await testBase.mint_token_to_user(testBase.user0, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user0, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token0_mint.publicKey, BigInt(100000));
+    await testBase.mint_token_to_user(testBase.user1, testBase.token1_mint.publicKey, BigInt(100000));
+    await testBase.create_amm(testBase.payer);
+    await testBase.create_pool(testBase.payer, testBase.payer.publicKey);
+    await testBase.create_pool_token();
+    await testBase.add_liquidity(testBase.user0, new BN(10000), new BN(10000), new BN(1000), new BN(1000));
+    await testBase.add_liquidity(testBase.user1, new BN(10000), new BN(10000), new BN(1000), new BN(1000));
+    await testBase.set_withdraw_cooldown(testBase.payer, new BN(3600));
+  });
+
+  it("rejects a withdrawal before the cooldown is over", async () => {
+    await expect_error(testBase.remove_liquidity(testBase.user1, new BN(0), new BN(0), new BN(1000)), "WithdrawNotRequested");
+
+    const lp_before = await balance(testBase.get_depositor_lp_account(testBase.user1));
+    await testBase.request_withdraw(testBase.user1, new BN(4000));
+    expect(lp_before - (await balance(testBase.get_depositor_lp_account(testBase.user1)))).to.equal(BigInt(4000));
+    const [request] = testBase.get_withdraw_request_pda(testBase.user1.publicKey);
+    await expect_error(testBase.remove_liquidity(testBase.user1, new BN(0), new BN(0), new BN(1000), request), "WithdrawCooldownActive");
+  });
+
+  it("rejects another user's request", async () => {
+    testBase.warp(3600);
+    const [request] = testBase.get_withdraw_request_pda(testBase.user1.publicKey);
+    await expect_error(testBase.remove_liquidity(testBase.user0, new BN(0), new BN(0), new BN(1000), request), "WithdrawNotRequested");
+  });
+
+  it("burns from the escrow after the cooldown and keeps a partial request open", async () => {
+    const [request] = testBase.get_withdraw_request_pda(testBase.user1.publicKey);
+    const escrow = getAssociatedTokenAddressSync(testBase.mint_lp_pda, request, true);
+    const lp_before = await balance(testBase.get_depositor_lp_account(testBase.user1));
+    const token0_before = await balance(testBase.user1_token0_account);
+
+    await testBase.remove_liquidity(testBase.user1, new BN(0), new BN(0), new BN(1000), request);
+    expect(await balance(escrow)).to.equal(BigInt(3000));
+    expect(testBase.client.getAccount(request)).to.not.be.null;
+    // the wallet LP is untouched, the burn comes out of the escrow
+    expect(await balance(testBase.get_depositor_lp_account(testBase.user1))).to.equal(lp_before);
+    expect(Number((await balance(testBase.user1_token0_account)) - token0_before)).to.be.within(990, 1000);
+
+    await testBase.remove_liquidity(testBase.user1, new BN(0), new BN(0), new BN(3000), request);
+    expect(testBase.client.getAccount(request)).to.be.null;
+    expect(testBase.client.getAccount(escrow)).to.be.null;
+  });
+
+  it("returns the LP on cancel", async () => {
+    const lp_before = await balance(testBase.get_depositor_lp_account(testBase.user1));
+    await testBase.request_withdraw(testBase.user1, new BN(2000));
+    await testBase.cancel_withdraw(testBase.user1);
+    expect(await balance(testBase.get_depositor_lp_account(testBase.user1))).to.equal(lp_before);
+    const [request] = testBase.get_withdraw_request_pda(testBase.user1.publicKey);
+    expect(testBase.client.getAccount(request)).to.be.null;
+  });
+});